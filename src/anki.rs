@@ -0,0 +1,1245 @@
+use crate::card::{Card, CardCollection};
+use crate::date::{current_timestamp, Date};
+use crate::deck::Deck;
+use crate::fsrs::{FSRSParams, FSRSState, ReviewAnswer, ReviewLogItem, ReviewTiming};
+use crate::parsing::{self, ClozeIterator, ClozeType};
+use crate::rand::fnv1a;
+use crate::tags::strip_tags;
+use rusqlite::Connection;
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::error::Error;
+use std::fs;
+use std::io::{Read, Write};
+use std::path::{Component, Path, PathBuf};
+
+// Newer Anki versions store the collection in a zstd compressed file which is not supported
+const COLLECTION_FILES: [&str; 2] = ["collection.anki21", "collection.anki2"];
+const FIELD_SEPARATOR: char = '\x1f';
+
+// Revlog entries of this type are manual reschedules and not reviews
+const REVLOG_MANUAL: i64 = 4;
+const QUEUE_SUSPENDED: i64 = -1;
+const QUEUE_LEARNING: i64 = 1;
+const QUEUE_REVIEW: i64 = 2;
+const QUEUE_DAY_LEARNING: i64 = 3;
+
+struct AnkiNote {
+    id: i64,
+    model_id: i64,
+    fields: Vec<String>,
+}
+
+struct AnkiCard {
+    id: i64,
+    note_id: i64,
+    deck_id: i64,
+    ord: i64,
+    card_type: i64,
+    queue: i64,
+    due: i64,
+    interval: i64,
+}
+
+struct AnkiCollection {
+    /// Creation time of the collection in seconds, review due dates are days since it
+    created: i64,
+    cloze_models: Vec<i64>,
+    decks: HashMap<i64, String>,
+    notes: Vec<AnkiNote>,
+    cards: Vec<AnkiCard>,
+    revlog: HashMap<i64, Vec<ReviewLogItem>>,
+}
+
+struct AnkiPackage {
+    collection: AnkiCollection,
+    /// Original file names and contents of the media files
+    media: Vec<(String, Vec<u8>)>,
+}
+
+/// Markdown of a single imported note and the cards it creates with their imported states
+pub struct ImportedNote {
+    pub file: PathBuf,
+    pub text: String,
+    pub cards: Vec<Card>,
+}
+
+pub struct ImportSummary {
+    pub notes: usize,
+    pub cards: usize,
+    /// Cards of other templates than the first one, such as reversed cards
+    pub skipped: usize,
+    /// Notes whose cards are already in the deck from an earlier import
+    pub existing: usize,
+}
+
+fn answer_from_ease(ease: i64) -> Option<ReviewAnswer> {
+    match ease {
+        1 => Some(ReviewAnswer::Again),
+        2 => Some(ReviewAnswer::Hard),
+        3 => Some(ReviewAnswer::Good),
+        4 => Some(ReviewAnswer::Easy),
+        _ => None,
+    }
+}
+
+fn decode_entities(input: &str) -> String {
+    let mut output = String::with_capacity(input.len());
+    let mut rest = input;
+    while let Some(start) = rest.find('&') {
+        output.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let end = match rest.bytes().take(10).position(|b| b == b';') {
+            Some(end) => end,
+            None => {
+                output.push('&');
+                rest = &rest[1..];
+                continue;
+            }
+        };
+        let entity = &rest[1..end];
+        let decoded = match entity {
+            "nbsp" => Some(' '),
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            _ => {
+                let number = if let Some(hex) = entity.strip_prefix("#x") {
+                    u32::from_str_radix(hex, 16).ok()
+                } else if let Some(decimal) = entity.strip_prefix('#') {
+                    decimal.parse().ok()
+                } else {
+                    None
+                };
+                number.and_then(char::from_u32)
+            }
+        };
+        match decoded {
+            Some(c) => {
+                output.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                output.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    output.push_str(rest);
+    output
+}
+
+fn ensure_newline(output: &mut String) {
+    if !output.is_empty() && !output.ends_with('\n') {
+        output.push('\n');
+    }
+}
+
+fn image_source(tag: &str) -> Option<&str> {
+    let start = tag.find("src=")? + 4;
+    let quote = tag[start..].chars().next()?;
+    if quote == '"' || quote == '\'' {
+        let end = tag[start + 1..].find(quote)?;
+        Some(&tag[start + 1..start + 1 + end])
+    } else {
+        tag[start..].split_whitespace().next()
+    }
+}
+
+/// Converts the HTML of an Anki field into markdown, unknown tags are dropped
+pub fn html_to_markdown(html: &str) -> String {
+    let mut output = String::with_capacity(html.len());
+    let mut rest = html;
+
+    while let Some(start) = rest.find('<') {
+        output.push_str(&decode_entities(&rest[..start]));
+        let end = match rest[start..].find('>') {
+            Some(end) => start + end,
+            None => {
+                rest = &rest[start..];
+                break;
+            }
+        };
+        let tag = &rest[start + 1..end];
+        rest = &rest[end + 1..];
+
+        let closing = tag.starts_with('/');
+        let name: String = tag
+            .trim_start_matches('/')
+            .chars()
+            .take_while(|c| c.is_ascii_alphanumeric())
+            .collect::<String>()
+            .to_lowercase();
+        match name.as_str() {
+            "br" => output.push('\n'),
+            "div" | "p" | "ul" | "ol" => ensure_newline(&mut output),
+            "li" if !closing => {
+                ensure_newline(&mut output);
+                output.push_str("- ");
+            }
+            "b" | "strong" => output.push_str("**"),
+            "i" | "em" => output.push('*'),
+            "img" => {
+                if let Some(source) = image_source(tag) {
+                    output.push_str(&format!("![]({})", source));
+                }
+            }
+            _ => (),
+        }
+    }
+    output.push_str(&decode_entities(rest));
+
+    let lines: Vec<&str> = output.trim().lines().map(|x| x.trim_end()).collect();
+    lines.join("\n")
+}
+
+/// Replaces Anki {{cN::answer::hint}} clozes with {{{answer}}}, returns the cloze number of each occurrence
+pub fn convert_cloze(text: &str) -> (String, Vec<usize>) {
+    let mut output = String::with_capacity(text.len());
+    let mut numbers = vec![];
+    let mut rest = text;
+
+    while let Some(start) = rest.find("{{c") {
+        let after = &rest[start + 3..];
+        let digits: String = after.chars().take_while(|c| c.is_ascii_digit()).collect();
+        let body = &after[digits.len()..];
+        let end = match body.strip_prefix("::").and_then(|x| x.find("}}")) {
+            Some(end) if !digits.is_empty() => end,
+            _ => {
+                output.push_str(&rest[..start + 3]);
+                rest = after;
+                continue;
+            }
+        };
+        let content = &body[2..2 + end];
+        let answer = match content.find("::") {
+            Some(hint) => &content[..hint],
+            None => content,
+        };
+
+        output.push_str(&rest[..start]);
+        output.push_str("{{{");
+        output.push_str(answer);
+        output.push_str("}}}");
+        numbers.push(digits.parse().unwrap());
+        rest = &body[2 + end + 2..];
+    }
+    output.push_str(rest);
+
+    (output, numbers)
+}
+
+/// Card in tmemo syntax, multiline when the card doesn't fit on a line
+pub fn card_markdown(front: &str, back: &str) -> String {
+    if front.contains('\n') || back.contains('\n') || front.contains(":: ") {
+        format!(":::\n{}\n:::\n{}\n:::\n", front, back)
+    } else {
+        format!("{}:: {}\n", front, back)
+    }
+}
+
+// Deck names are split into folders, the last part is the file
+fn deck_path(name: &str) -> PathBuf {
+    let mut path = PathBuf::new();
+    for part in name.split("::") {
+        let part: String = part
+            .trim()
+            .chars()
+            .map(|c| if "/\\:*?\"<>|".contains(c) { '-' } else { c })
+            .collect();
+        // Parts such as .. would leave the target folder
+        match Path::new(&part).components().next() {
+            Some(Component::Normal(_)) => path.push(&part),
+            Some(_) => continue,
+            None => path.push("Default"),
+        }
+    }
+    if path.as_os_str().is_empty() {
+        path.push("Default");
+    }
+    path.set_extension("md");
+    path
+}
+
+// Same as the relative path of the file when the cards are parsed from the working directory
+fn file_heading(path: &Path) -> String {
+    let path = std::env::current_dir()
+        .ok()
+        .and_then(|dir| path.strip_prefix(dir).ok())
+        .unwrap_or(path);
+    path.components()
+        .filter_map(|x| match x {
+            Component::Normal(part) => Some(part.to_string_lossy().to_string()),
+            _ => None,
+        })
+        .collect::<Vec<String>>()
+        .join("/")
+}
+
+/// Cards of the markdown of a note written into the file
+fn note_cards(text: &str, file_path: &Path, today: Date) -> Vec<Card> {
+    let parsed = parsing::parse_cards(text, today, Some(file_heading(file_path)));
+    match CardCollection::from(parsed) {
+        Ok(collection) => collection.cards,
+        Err(_) => vec![],
+    }
+}
+
+/// Replays the Anki review history with FSRS so that the card keeps its memory state
+fn card_state(
+    card: &AnkiCard,
+    log: &[ReviewLogItem],
+    created: i64,
+    params: &FSRSParams,
+    today: Date,
+) -> FSRSState {
+    let mut state = FSRSState::new(log.first().map_or(today, |x| x.day));
+
+    for item in log {
+        state.review(item.answer.clone(), &item.day, true, 1.0, params);
+        if let (Some(timestamp), Some(duration)) = (item.timestamp, item.duration) {
+            state.record_timing(&ReviewTiming {
+                timestamp,
+                duration,
+            });
+        }
+    }
+
+    let created_day = Date::from_timestamp(created * 1000);
+    let due = match card.queue {
+        QUEUE_LEARNING => Some(Date::from_timestamp(card.due * 1000)),
+        QUEUE_REVIEW | QUEUE_DAY_LEARNING => created_day.checked_add_days(card.due as i32),
+        _ => None,
+    };
+
+    if log.is_empty() && card.card_type != 0 {
+        // The revlog has been cleared, so start from the current interval
+        state.complete_history = false;
+        state.difficulty = 5.0;
+        state.stability = card.interval.max(1) as f64;
+        if let Some(due) = due {
+            state.last_review = due.checked_add_days(-(card.interval as i32)).unwrap();
+        }
+    }
+
+    // Keep the schedule of Anki so that the reviews don't bunch up after importing
+    if let Some(due) = due {
+        state.review_date = due;
+    }
+    if card.queue == QUEUE_SUSPENDED {
+        state.suspend(None);
+    }
+
+    state
+}
+
+fn read_collection(conn: &Connection) -> Result<AnkiCollection, Box<dyn Error>> {
+    let (created, models, decks): (i64, String, String) =
+        conn.query_row("SELECT crt, models, decks FROM col", [], |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+        })?;
+
+    let mut cloze_models = vec![];
+    let mut deck_names = HashMap::new();
+    let models: Value = serde_json::from_str(&models).unwrap_or(Value::Null);
+    if let Some(models) = models.as_object() {
+        for (id, model) in models {
+            if model["type"].as_i64() == Some(1) {
+                cloze_models.push(id.parse()?);
+            }
+        }
+    }
+    let decks: Value = serde_json::from_str(&decks).unwrap_or(Value::Null);
+    match decks.as_object() {
+        Some(decks) if !decks.is_empty() => {
+            for (id, deck) in decks {
+                let name = deck["name"].as_str().unwrap_or("Default");
+                deck_names.insert(id.parse()?, name.to_string());
+            }
+        }
+        _ => {
+            // Newer collections keep the decks in their own table with the parts separated by 0x1f
+            let mut statement = conn.prepare("SELECT id, name FROM decks")?;
+            let rows = statement.query_map([], |row| {
+                Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+            })?;
+            for row in rows {
+                let (id, name) = row?;
+                deck_names.insert(id, name.replace(FIELD_SEPARATOR, "::"));
+            }
+        }
+    }
+
+    let mut statement = conn.prepare("SELECT id, mid, flds FROM notes ORDER BY id")?;
+    let notes = statement
+        .query_map([], |row| {
+            let fields: String = row.get(2)?;
+            Ok(AnkiNote {
+                id: row.get(0)?,
+                model_id: row.get(1)?,
+                fields: fields
+                    .split(FIELD_SEPARATOR)
+                    .map(|x| x.to_string())
+                    .collect(),
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut statement = conn
+        .prepare("SELECT id, nid, did, ord, type, queue, due, ivl FROM cards ORDER BY nid, ord")?;
+    let cards = statement
+        .query_map([], |row| {
+            Ok(AnkiCard {
+                id: row.get(0)?,
+                note_id: row.get(1)?,
+                deck_id: row.get(2)?,
+                ord: row.get(3)?,
+                card_type: row.get(4)?,
+                queue: row.get(5)?,
+                due: row.get(6)?,
+                interval: row.get(7)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut revlog: HashMap<i64, Vec<ReviewLogItem>> = HashMap::new();
+    let mut statement = conn.prepare("SELECT id, cid, ease, time, type FROM revlog ORDER BY id")?;
+    let rows = statement.query_map([], |row| {
+        Ok((
+            row.get::<_, i64>(0)?,
+            row.get::<_, i64>(1)?,
+            row.get::<_, i64>(2)?,
+            row.get::<_, i64>(3)?,
+            row.get::<_, i64>(4)?,
+        ))
+    })?;
+    for row in rows {
+        let (timestamp, card_id, ease, time, review_type) = row?;
+        let answer = match answer_from_ease(ease) {
+            Some(answer) if review_type != REVLOG_MANUAL => answer,
+            _ => continue,
+        };
+        revlog.entry(card_id).or_default().push(ReviewLogItem {
+            answer,
+            day: Date::from_timestamp(timestamp),
+            timestamp: Some(timestamp),
+            duration: Some(time.max(0) as u32),
+        });
+    }
+
+    Ok(AnkiCollection {
+        created,
+        cloze_models,
+        decks: deck_names,
+        notes,
+        cards,
+        revlog,
+    })
+}
+
+fn convert_collection(
+    collection: &AnkiCollection,
+    deck: &Deck,
+    target: &Path,
+    today: Date,
+) -> (Vec<ImportedNote>, usize) {
+    let mut cards_by_note: HashMap<i64, Vec<&AnkiCard>> = HashMap::new();
+    for card in &collection.cards {
+        cards_by_note.entry(card.note_id).or_default().push(card);
+    }
+
+    let mut imported = vec![];
+    let mut skipped = 0;
+    let empty = vec![];
+    for note in &collection.notes {
+        let cards = match cards_by_note.get(&note.id) {
+            Some(cards) => cards,
+            None => continue,
+        };
+        let deck_name = collection
+            .decks
+            .get(&cards[0].deck_id)
+            .map_or("Default", |x| x.as_str());
+
+        let first_field = html_to_markdown(note.fields.first().map_or("", |x| x.as_str()));
+        let (text, anki_cards) = if collection.cloze_models.contains(&note.model_id) {
+            let (back, numbers) = convert_cloze(&first_field);
+            let anki_cards: Vec<Option<&AnkiCard>> = numbers
+                .iter()
+                .map(|number| {
+                    cards
+                        .iter()
+                        .find(|card| card.ord == *number as i64 - 1)
+                        .copied()
+                })
+                .collect();
+            (card_markdown("", &back), anki_cards)
+        } else {
+            let back = html_to_markdown(note.fields.get(1).map_or("", |x| x.as_str()));
+            skipped += cards.len() - 1;
+            (card_markdown(&first_field, &back), vec![Some(cards[0])])
+        };
+
+        // The history is replayed with the params of the preset of each card
+        let file = deck_path(deck_name);
+        let mut note_cards = note_cards(&text, &target.join(&file), today);
+        for (card, anki_card) in note_cards.iter_mut().zip(anki_cards) {
+            card.fsrs_state = match anki_card {
+                Some(anki_card) => {
+                    let log = collection.revlog.get(&anki_card.id).unwrap_or(&empty);
+                    card_state(
+                        anki_card,
+                        log,
+                        collection.created,
+                        deck.params_for(card),
+                        today,
+                    )
+                }
+                None => FSRSState::new(today),
+            };
+        }
+
+        imported.push(ImportedNote {
+            file,
+            text,
+            cards: note_cards,
+        });
+    }
+
+    (imported, skipped)
+}
+
+fn read_apkg(path: &Path) -> Result<AnkiPackage, Box<dyn Error>> {
+    let mut archive = zip::ZipArchive::new(fs::File::open(path)?)?;
+
+    let mut data = None;
+    for name in COLLECTION_FILES {
+        if let Ok(mut file) = archive.by_name(name) {
+            let mut buffer = vec![];
+            file.read_to_end(&mut buffer)?;
+            data = Some(buffer);
+            break;
+        }
+    }
+    let data = match data {
+        Some(data) => data,
+        None => {
+            return Err(String::from(
+                "No collection found, export the deck with support for older Anki versions",
+            )
+            .into())
+        }
+    };
+
+    // SQLite needs a file to open
+    let temp_path = std::env::temp_dir().join(format!("tmemo-import-{}.anki2", std::process::id()));
+    fs::write(&temp_path, data)?;
+    let collection = Connection::open(&temp_path)
+        .map_err(|x| x.into())
+        .and_then(|conn| read_collection(&conn));
+    fs::remove_file(&temp_path)?;
+    let collection = collection?;
+
+    // Media files are stored by number with a JSON map to the original names
+    let mut media = vec![];
+    let names: HashMap<String, String> = match archive.by_name("media") {
+        Ok(mut file) => {
+            let mut text = String::new();
+            file.read_to_string(&mut text)?;
+            serde_json::from_str(&text).unwrap_or_default()
+        }
+        Err(_) => HashMap::new(),
+    };
+    for (number, name) in names {
+        if let Ok(mut file) = archive.by_name(&number) {
+            let mut buffer = vec![];
+            file.read_to_end(&mut buffer)?;
+            media.push((name, buffer));
+        }
+    }
+
+    Ok(AnkiPackage { collection, media })
+}
+
+/// Imports the notes of an .apkg into markdown files in the target folder and their cards into the deck
+pub fn import_apkg(
+    path: &Path,
+    target: &Path,
+    deck: &mut Deck,
+) -> Result<ImportSummary, Box<dyn Error>> {
+    let AnkiPackage { collection, media } = read_apkg(path)?;
+    let today = Date::now();
+    let (notes, skipped) = convert_collection(&collection, deck, target, today);
+
+    // Notes imported before are skipped so that importing again doesn't duplicate them
+    let mut keys: HashSet<String> = deck.cards.iter().map(|x| x.content.key()).collect();
+    let mut summary = ImportSummary {
+        notes: 0,
+        cards: 0,
+        skipped,
+        existing: 0,
+    };
+    let mut new_notes = vec![];
+    for note in notes {
+        if note.cards.iter().any(|x| keys.contains(&x.content.key())) {
+            summary.existing += 1;
+            continue;
+        }
+        if !note
+            .file
+            .components()
+            .all(|x| matches!(x, Component::Normal(_)))
+        {
+            return Err(format!(
+                "Deck path {} is outside the target folder",
+                note.file.display()
+            )
+            .into());
+        }
+        keys.extend(note.cards.iter().map(|x| x.content.key()));
+        new_notes.push(note);
+    }
+
+    let mut files: HashMap<PathBuf, String> = HashMap::new();
+    for note in &new_notes {
+        files
+            .entry(target.join(&note.file))
+            .or_default()
+            .push_str(&format!("{}\n", note.text));
+    }
+    for (file_path, text) in &files {
+        if let Some(parent) = file_path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut contents = fs::read_to_string(file_path).unwrap_or_default();
+        if !contents.is_empty() && !contents.ends_with("\n\n") {
+            contents.push('\n');
+        }
+        contents.push_str(text);
+        fs::write(file_path, contents)?;
+    }
+    for (name, data) in media {
+        // Only the file name is used so that the media can't be written outside the target folder
+        let file_name = match Path::new(&name).file_name() {
+            Some(file_name) => file_name,
+            None => {
+                println!("Skipped media file with an invalid name {}", name);
+                continue;
+            }
+        };
+        fs::create_dir_all(target)?;
+        fs::write(target.join(file_name), data)?;
+    }
+
+    for note in new_notes {
+        summary.notes += 1;
+        summary.cards += note.cards.len();
+        deck.cards.extend(note.cards);
+    }
+    deck.cards.sort();
+
+    Ok(summary)
+}
+
+// Fixed ids so that the note types are matched when exporting again
+const BASIC_MODEL_ID: i64 = 1_700_000_000_001;
+const CLOZE_MODEL_ID: i64 = 1_700_000_000_002;
+const DEFAULT_FACTOR: i64 = 2500;
+
+/// Note built from a card or from the base card of cloze cards, with the cloze index and state of each card
+pub struct ExportNote {
+    pub deck: String,
+    pub cloze: bool,
+    pub fields: [String; 2],
+    pub guid: String,
+    /// Anki tags separated by spaces, the tags of the card with `::` for nested tags
+    pub tags: String,
+    pub cards: Vec<(usize, FSRSState)>,
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('\t', "    ")
+        .replace('\n', "<br>")
+}
+
+/// The card prefix as an Anki deck, the path of the file without the extension followed by the headings
+pub fn prefix_to_deck(prefix: &str) -> String {
+    let parts: Vec<String> = prefix
+        .split(" > ")
+        .enumerate()
+        .map(|(i, part)| {
+            if i == 0 {
+                // Folders of the file become parent decks
+                part.trim().trim_end_matches(".md").replace('/', "::")
+            } else {
+                part.trim().to_string()
+            }
+        })
+        .collect();
+    parts.join("::")
+}
+
+/// Replaces the tmemo clozes of the text with numbered Anki clozes, in the same order as the cloze cards
+pub fn to_anki_cloze(text: &str) -> String {
+    let cloze_type = if text.contains("{{{") && text.contains("}}}") {
+        ClozeType::TripleBrace
+    } else {
+        ClozeType::TripleParen
+    };
+
+    let mut output = String::new();
+    let mut previous_end = 0;
+    for (i, item) in ClozeIterator::new(cloze_type, text).enumerate() {
+        output.push_str(&escape_html(&text[previous_end..item.cloze_start]));
+        output.push_str(&format!("{{{{c{}::{}}}}}", i + 1, escape_html(item.clozed)));
+        previous_end = item.cloze_end;
+    }
+    output.push_str(&escape_html(&text[previous_end..]));
+    output
+}
+
+fn anki_tags(tags: &[String]) -> String {
+    let mut output = String::from("tmemo");
+    for tag in tags {
+        output.push(' ');
+        output.push_str(&tag.replace('/', "::"));
+    }
+    output
+}
+
+// FNV-1a, stable between runs so that importing again updates the notes in Anki
+fn note_guid(key: &str) -> String {
+    format!("tmemo{:016x}", fnv1a(key))
+}
+
+/// Collects the notes of the deck, the base cards of cloze cards have to be loaded
+pub fn collect_notes(deck: &Deck) -> Vec<ExportNote> {
+    let mut notes = vec![];
+    let mut cloze_notes: BTreeMap<usize, ExportNote> = BTreeMap::new();
+
+    for card in &deck.cards {
+        let content = &card.content;
+        match (content.base, deck.base_cards.get(content.base.unwrap_or(0))) {
+            // Reversed cards are exported as two basic notes
+            (Some(base), Some(base_card)) if !base_card.content.reverse => {
+                let note = cloze_notes.entry(base).or_insert_with(|| {
+                    let mut text = String::new();
+                    let front = strip_tags(&base_card.content.front);
+                    if !front.trim().is_empty() {
+                        text = escape_html(front.trim()) + "<br><br>";
+                    }
+                    text.push_str(&to_anki_cloze(strip_tags(&base_card.content.back).trim()));
+                    ExportNote {
+                        deck: prefix_to_deck(&base_card.content.prefix),
+                        cloze: true,
+                        fields: [text, String::new()],
+                        guid: note_guid(&base_card.content.key()),
+                        tags: anki_tags(&base_card.content.tags),
+                        cards: vec![],
+                    }
+                });
+                note.cards
+                    .push((content.cloze_index.unwrap_or(0), card.fsrs_state.clone()));
+            }
+            _ => notes.push(ExportNote {
+                deck: prefix_to_deck(&content.prefix),
+                cloze: false,
+                fields: [
+                    escape_html(strip_tags(&content.front).trim()),
+                    escape_html(strip_tags(&content.back).trim()),
+                ],
+                guid: note_guid(&content.key()),
+                tags: anki_tags(&content.tags),
+                cards: vec![(0, card.fsrs_state.clone())],
+            }),
+        }
+    }
+
+    notes.extend(cloze_notes.into_values());
+    notes
+}
+
+/// Anki text import file with the note type, deck and tags in their own columns
+pub fn notes_to_text(notes: &[ExportNote]) -> String {
+    let mut output = String::from(
+        "#separator:tab\n#html:true\n#guid column:1\n#notetype column:2\n#deck column:3\n#tags column:6\n",
+    );
+    for note in notes {
+        output.push_str(&format!(
+            "{}\t{}\t{}\t{}\t{}\t{}\n",
+            note.guid,
+            if note.cloze { "Cloze" } else { "Basic" },
+            note.deck,
+            note.fields[0],
+            note.fields[1],
+            note.tags
+        ));
+    }
+    output
+}
+
+fn model_json(id: i64, cloze: bool) -> Value {
+    let (name, fields, template) = if cloze {
+        (
+            "tmemo Cloze",
+            ["Text", "Back Extra"],
+            json!({"name": "Cloze", "ord": 0, "qfmt": "{{cloze:Text}}", "afmt": "{{cloze:Text}}<br>\n{{Back Extra}}", "bqfmt": "", "bafmt": "", "did": null}),
+        )
+    } else {
+        (
+            "tmemo Basic",
+            ["Front", "Back"],
+            json!({"name": "Card 1", "ord": 0, "qfmt": "{{Front}}", "afmt": "{{FrontSide}}\n\n<hr id=answer>\n\n{{Back}}", "bqfmt": "", "bafmt": "", "did": null}),
+        )
+    };
+    let fields: Vec<Value> = fields
+        .iter()
+        .enumerate()
+        .map(|(ord, name)| {
+            json!({"name": name, "ord": ord, "sticky": false, "rtl": false, "font": "Arial", "size": 20, "media": []})
+        })
+        .collect();
+    json!({
+        "id": id,
+        "name": name,
+        "type": if cloze { 1 } else { 0 },
+        "mod": 0,
+        "usn": -1,
+        "sortf": 0,
+        "did": 1,
+        "tmpls": [template],
+        "flds": fields,
+        "css": ".card { font-family: arial; font-size: 20px; text-align: center; }",
+        "latexPre": "",
+        "latexPost": "",
+        "latexsvg": false,
+        "req": [[0, "any", [0]]],
+        "tags": [],
+        "vers": []
+    })
+}
+
+fn deck_json(id: i64, name: &str) -> Value {
+    json!({
+        "id": id,
+        "name": name,
+        "mod": 0,
+        "usn": -1,
+        "lrnToday": [0, 0],
+        "revToday": [0, 0],
+        "newToday": [0, 0],
+        "timeToday": [0, 0],
+        "collapsed": false,
+        "browserCollapsed": false,
+        "desc": "",
+        "dyn": 0,
+        "conf": 1,
+        "extendNew": 0,
+        "extendRev": 0
+    })
+}
+
+fn ease_from_answer(answer: &ReviewAnswer) -> i64 {
+    match answer {
+        ReviewAnswer::Again => 1,
+        ReviewAnswer::Hard => 2,
+        ReviewAnswer::Good => 3,
+        ReviewAnswer::Easy => 4,
+        ReviewAnswer::Bury => 0,
+    }
+}
+
+const SCHEMA: &str = "
+CREATE TABLE col (id integer primary key, crt integer not null, mod integer not null, scm integer not null, ver integer not null, dty integer not null, usn integer not null, ls integer not null, conf text not null, models text not null, decks text not null, dconf text not null, tags text not null);
+CREATE TABLE notes (id integer primary key, guid text not null, mid integer not null, mod integer not null, usn integer not null, tags text not null, flds text not null, sfld integer not null, csum integer not null, flags integer not null, data text not null);
+CREATE TABLE cards (id integer primary key, nid integer not null, did integer not null, ord integer not null, mod integer not null, usn integer not null, type integer not null, queue integer not null, due integer not null, ivl integer not null, factor integer not null, reps integer not null, lapses integer not null, left integer not null, odue integer not null, odid integer not null, flags integer not null, data text not null);
+CREATE TABLE revlog (id integer primary key, cid integer not null, usn integer not null, ease integer not null, ivl integer not null, lastIvl integer not null, factor integer not null, time integer not null, type integer not null);
+CREATE TABLE graves (usn integer not null, oid integer not null, type integer not null);
+";
+
+/// Writes the notes into an empty Anki collection, optionally with the due dates, intervals and review logs
+fn write_collection(
+    conn: &mut Connection,
+    notes: &[ExportNote],
+    schedule: bool,
+    today: Date,
+) -> Result<(), Box<dyn Error>> {
+    let now = current_timestamp();
+    // Day boundary of the collection is early in the morning of today
+    let created = today.noon_timestamp() / 1000 - 6 * 3600;
+
+    let mut deck_ids: BTreeMap<&str, i64> = BTreeMap::new();
+    for note in notes {
+        let next_id = now + deck_ids.len() as i64;
+        deck_ids.entry(note.deck.as_str()).or_insert(next_id);
+    }
+    let mut decks = serde_json::Map::new();
+    decks.insert("1".to_string(), deck_json(1, "Default"));
+    for (name, id) in &deck_ids {
+        decks.insert(id.to_string(), deck_json(*id, name));
+    }
+    let mut models = serde_json::Map::new();
+    models.insert(
+        BASIC_MODEL_ID.to_string(),
+        model_json(BASIC_MODEL_ID, false),
+    );
+    models.insert(CLOZE_MODEL_ID.to_string(), model_json(CLOZE_MODEL_ID, true));
+    let dconf = json!({"1": {
+        "id": 1, "name": "Default", "mod": 0, "usn": -1, "maxTaken": 60, "autoplay": true,
+        "timer": 0, "replayq": true, "dyn": false,
+        "new": {"delays": [1, 10], "ints": [1, 4, 0], "initialFactor": DEFAULT_FACTOR, "order": 1, "perDay": 20, "bury": false},
+        "rev": {"perDay": 200, "ease4": 1.3, "maxIvl": 36500, "hardFactor": 1.2, "bury": false},
+        "lapse": {"delays": [10], "mult": 0, "minInt": 1, "leechFails": 8, "leechAction": 1}
+    }});
+    let conf = json!({"curDeck": 1, "activeDecks": [1], "nextPos": 1, "sortType": "noteFld", "sortBackwards": false, "addToCur": true, "collapseTime": 1200, "newSpread": 0, "dueCounts": true, "timeLim": 0, "estTimes": true, "schedVer": 2});
+
+    let tx = conn.transaction()?;
+    tx.execute_batch(SCHEMA)?;
+    tx.execute(
+        "INSERT INTO col VALUES (1, ?1, ?2, ?2, 11, 0, 0, 0, ?3, ?4, ?5, ?6, '{}')",
+        rusqlite::params![
+            created,
+            now,
+            conf.to_string(),
+            Value::Object(models).to_string(),
+            Value::Object(decks).to_string(),
+            dconf.to_string()
+        ],
+    )?;
+
+    let mut card_id = now;
+    let mut revlog_id = 0;
+    for (note_id, note) in (now..).zip(notes) {
+        let model_id = if note.cloze {
+            CLOZE_MODEL_ID
+        } else {
+            BASIC_MODEL_ID
+        };
+        // Anki recalculates the checksum of the sort field when importing
+        tx.execute(
+            "INSERT INTO notes VALUES (?1, ?2, ?3, ?4, -1, ?5, ?6, ?7, 0, 0, '')",
+            rusqlite::params![
+                note_id,
+                note.guid,
+                model_id,
+                now / 1000,
+                format!(" {} ", note.tags),
+                note.fields.join(&FIELD_SEPARATOR.to_string()),
+                html_to_markdown(&note.fields[0])
+            ],
+        )?;
+
+        for (ord, state) in &note.cards {
+            card_id += 1;
+            let deck_id = deck_ids[note.deck.as_str()];
+            let (card_type, mut queue, due, interval) = if schedule && !state.is_new() {
+                let interval = (state.review_date.day - state.last_review.day).max(1);
+                (2, 2, (state.review_date.day - today.day) as i64, interval)
+            } else {
+                (0, 0, note_id - now + 1, 0)
+            };
+            if state.is_suspended(&today) {
+                queue = QUEUE_SUSPENDED;
+            }
+            let reviews = if schedule { state.review_log.len() } else { 0 };
+            let lapses = if schedule { state.lapses() } else { 0 };
+            let factor = if card_type == 2 { DEFAULT_FACTOR } else { 0 };
+            tx.execute(
+                "INSERT INTO cards VALUES (?1, ?2, ?3, ?4, ?5, -1, ?6, ?7, ?8, ?9, ?10, ?11, ?12, 0, 0, 0, 0, '')",
+                rusqlite::params![
+                    card_id,
+                    note_id,
+                    deck_id,
+                    *ord as i64,
+                    now / 1000,
+                    card_type,
+                    queue,
+                    due,
+                    interval,
+                    factor,
+                    reviews as i64,
+                    lapses
+                ],
+            )?;
+
+            if !schedule {
+                continue;
+            }
+            for (i, item) in state.review_log.iter().enumerate() {
+                let ease = ease_from_answer(&item.answer);
+                if ease == 0 {
+                    continue;
+                }
+                // Revlog ids are the review times and have to be unique
+                let timestamp = item.timestamp.unwrap_or(item.day.noon_timestamp());
+                revlog_id = timestamp.max(revlog_id + 1);
+                tx.execute(
+                    "INSERT INTO revlog VALUES (?1, ?2, -1, ?3, 0, 0, 0, ?4, ?5)",
+                    rusqlite::params![
+                        revlog_id,
+                        card_id,
+                        ease,
+                        item.duration.unwrap_or(0),
+                        if i == 0 { 0 } else { 1 }
+                    ],
+                )?;
+            }
+        }
+    }
+    tx.commit()?;
+
+    Ok(())
+}
+
+/// Writes the notes as an .apkg package
+pub fn export_apkg(
+    notes: &[ExportNote],
+    path: &Path,
+    schedule: bool,
+) -> Result<(), Box<dyn Error>> {
+    let temp_path = std::env::temp_dir().join(format!("tmemo-export-{}.anki2", std::process::id()));
+    let _ = fs::remove_file(&temp_path);
+    let result = Connection::open(&temp_path)
+        .map_err(|x| x.into())
+        .and_then(|mut conn| write_collection(&mut conn, notes, schedule, Date::now()));
+    let data = result.and_then(|_| fs::read(&temp_path).map_err(|x| x.into()));
+    fs::remove_file(&temp_path)?;
+    let data = data?;
+
+    let mut writer = zip::ZipWriter::new(fs::File::create(path)?);
+    let options =
+        zip::write::FileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+    writer.start_file("collection.anki2", options)?;
+    writer.write_all(&data)?;
+    writer.start_file("media", options)?;
+    writer.write_all(b"{}")?;
+    writer.finish()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::preset::Preset;
+
+    fn test_collection() -> Connection {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(
+            "CREATE TABLE col (crt INTEGER, models TEXT, decks TEXT);
+             CREATE TABLE notes (id INTEGER, mid INTEGER, flds TEXT);
+             CREATE TABLE cards (id INTEGER, nid INTEGER, did INTEGER, ord INTEGER, type INTEGER, queue INTEGER, due INTEGER, ivl INTEGER);
+             CREATE TABLE revlog (id INTEGER, cid INTEGER, ease INTEGER, time INTEGER, type INTEGER);",
+        )
+        .unwrap();
+        // Collection created on 2024-01-01
+        conn.execute(
+            "INSERT INTO col VALUES (1704103200, ?1, ?2)",
+            [
+                r#"{"1": {"type": 0, "name": "Basic"}, "2": {"type": 1, "name": "Cloze"}}"#,
+                r#"{"10": {"name": "Languages::Spanish"}}"#,
+            ],
+        )
+        .unwrap();
+        conn.execute_batch(
+            "INSERT INTO notes VALUES (100, 1, 'perro' || char(31) || 'dog');
+             INSERT INTO notes VALUES (101, 2, '{{c1::Madrid}} is the capital of {{c2::Spain::country}}<br>' || char(31) || '');
+             INSERT INTO cards VALUES (1000, 100, 10, 0, 2, 2, 10, 5);
+             INSERT INTO cards VALUES (1001, 100, 10, 1, 0, 0, 0, 0);
+             INSERT INTO cards VALUES (1010, 101, 10, 0, 0, 0, 0, 0);
+             INSERT INTO cards VALUES (1011, 101, 10, 1, 2, -1, 20, 3);
+             INSERT INTO revlog VALUES (1704189600000, 1000, 3, 5000, 0);
+             INSERT INTO revlog VALUES (1704535200000, 1000, 3, 4000, 1);
+             INSERT INTO revlog VALUES (1704535300000, 1000, 0, 0, 4);",
+        )
+        .unwrap();
+        conn
+    }
+
+    #[test]
+    fn html_is_converted() {
+        assert_eq!(
+            html_to_markdown("a<br>b<div>c&nbsp;&amp;&lt;d&gt;</div>"),
+            "a\nb\nc &<d>"
+        );
+        assert_eq!(
+            html_to_markdown("<b>bold</b> <i>it</i> <img src=\"cat.png\">"),
+            "**bold** *it* ![](cat.png)"
+        );
+        assert_eq!(
+            html_to_markdown("<ul><li>a</li><li>b</li></ul>"),
+            "- a\n- b"
+        );
+    }
+
+    #[test]
+    fn clozes_are_converted() {
+        assert_eq!(
+            convert_cloze("{{c2::a}} and {{c1::b::hint}} {{c"),
+            ("{{{a}}} and {{{b}}} {{c".to_string(), vec![2, 1])
+        );
+    }
+
+    #[test]
+    fn collection_is_imported() {
+        let collection = read_collection(&test_collection()).unwrap();
+        let today = Date::from_ymd_opt(2024, 1, 10).unwrap();
+        let (notes, skipped) =
+            convert_collection(&collection, &Deck::new(), Path::new("anki"), today);
+        assert_eq!(skipped, 1);
+        assert_eq!(notes.len(), 2);
+        assert_eq!(notes[0].file, PathBuf::from("Languages/Spanish.md"));
+        assert_eq!(notes[0].text, "perro:: dog\n");
+        assert_eq!(
+            notes[1].text,
+            ":: {{{Madrid}}} is the capital of {{{Spain}}}\n"
+        );
+
+        let basic = &notes[0].cards[0].fsrs_state;
+        assert_eq!(basic.review_log.len(), 2);
+        assert_eq!(basic.review_log[0].duration, Some(5000));
+        assert!(basic.stability > 0.0);
+        assert_eq!(basic.review_date, Date::from_ymd_opt(2024, 1, 11).unwrap());
+
+        let cloze = &notes[1].cards;
+        assert_eq!(cloze.len(), 2);
+        assert!(cloze[0].fsrs_state.is_new());
+        assert!(cloze[1].fsrs_state.suspended);
+        assert!(!cloze[1].fsrs_state.complete_history);
+        assert_eq!(cloze[1].fsrs_state.stability, 3.0);
+        assert_eq!(cloze[1].content.prefix, "anki/Languages/Spanish.md");
+        assert_eq!(cloze[1].content.back, "Spain");
+
+        // The history is replayed with the params of the preset of the card
+        let mut deck = Deck::new();
+        let mut params = FSRSParams::new();
+        params.w[2] *= 2.0;
+        deck.presets.push(Preset {
+            name: "spanish".to_string(),
+            patterns: vec!["anki/Languages/**".to_string()],
+            params,
+        });
+        let (notes, _) = convert_collection(&collection, &deck, Path::new("anki"), today);
+        assert!(notes[0].cards[0].fsrs_state.stability > basic.stability);
+    }
+
+    #[test]
+    fn imported_paths_stay_in_target() {
+        assert_eq!(deck_path("..::a"), PathBuf::from("a.md"));
+        assert_eq!(deck_path(".::..::x::"), PathBuf::from("x/Default.md"));
+        assert_eq!(deck_path(".."), PathBuf::from("Default.md"));
+
+        let dir = std::env::temp_dir().join(format!("tmemo-import-test-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let target = dir.join("anki");
+        let package = dir.join("test.apkg");
+        fs::create_dir_all(&dir).unwrap();
+        let data = {
+            let path = dir.join("collection.anki2");
+            let source = test_collection();
+            source
+                .execute("VACUUM INTO ?1", [path.to_str().unwrap()])
+                .unwrap();
+            fs::read(path).unwrap()
+        };
+        let mut writer = zip::ZipWriter::new(fs::File::create(&package).unwrap());
+        let options = zip::write::FileOptions::default();
+        writer.start_file("collection.anki2", options).unwrap();
+        writer.write_all(&data).unwrap();
+        writer.start_file("media", options).unwrap();
+        writer.write_all(br#"{"0": "../../outside.png"}"#).unwrap();
+        writer.start_file("0", options).unwrap();
+        writer.write_all(b"png").unwrap();
+        writer.finish().unwrap();
+
+        let mut deck = Deck::new();
+        let summary = import_apkg(&package, &target, &mut deck).unwrap();
+        assert_eq!((summary.notes, summary.cards, summary.existing), (2, 3, 0));
+        assert!(target.join("outside.png").exists());
+        assert!(!dir.parent().unwrap().join("outside.png").exists());
+
+        // Importing again doesn't duplicate the notes
+        let file = target.join("Languages/Spanish.md");
+        let text = fs::read_to_string(&file).unwrap();
+        let summary = import_apkg(&package, &target, &mut deck).unwrap();
+        assert_eq!((summary.notes, summary.cards, summary.existing), (0, 0, 2));
+        assert_eq!(fs::read_to_string(&file).unwrap(), text);
+        assert_eq!(deck.cards.len(), 3);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn clozes_are_exported() {
+        assert_eq!(
+            to_anki_cloze("{{{a}}} < {{{b}}}"),
+            "{{c1::a}} &lt; {{c2::b}}"
+        );
+        assert_eq!(to_anki_cloze("x (((a)))"), "x {{c1::a}}");
+        assert_eq!(
+            prefix_to_deck("spanish.md > Verbs > Irregular"),
+            "spanish::Verbs::Irregular"
+        );
+        assert_eq!(
+            prefix_to_deck("languages/spanish.md > Verbs"),
+            "languages::spanish::Verbs"
+        );
+    }
+
+    #[test]
+    fn exported_collection_can_be_imported() {
+        let today = Date::from_ymd_opt(2024, 1, 10).unwrap();
+        let mut deck = Deck::new();
+        let mut cards = parsing::parse_cards(
+            "# Verbs\nser:: to be #spanish/verbs\n:: {{{Madrid}}} is in {{{Spain}}}\n",
+            today,
+            Some("spanish.md".to_string()),
+        );
+        let params = FSRSParams::new();
+        cards[0]
+            .fsrs_state
+            .review(ReviewAnswer::Good, &today, true, 1.0, &params);
+        deck.replace_cards(CardCollection::from(cards).unwrap())
+            .unwrap();
+
+        let notes = collect_notes(&deck);
+        assert_eq!(notes.len(), 2);
+        assert!(notes[1].cloze);
+        assert_eq!(notes[1].fields[0], "{{c1::Madrid}} is in {{c2::Spain}}");
+        assert_eq!(notes[1].cards.len(), 2);
+        assert!(notes_to_text(&notes)
+            .contains("\tBasic\tspanish::Verbs\tser\tto be\ttmemo spanish::verbs\n"));
+
+        let mut conn = Connection::open_in_memory().unwrap();
+        write_collection(&mut conn, &notes, true, today).unwrap();
+        let tags: String = conn
+            .query_row(
+                "SELECT tags FROM notes WHERE guid = ?1",
+                [&notes[0].guid],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(tags, " tmemo spanish::verbs ");
+        let collection = read_collection(&conn).unwrap();
+        let (imported, skipped) =
+            convert_collection(&collection, &Deck::new(), Path::new(""), today);
+        assert_eq!(skipped, 0);
+        assert_eq!(imported[0].file, PathBuf::from("spanish/Verbs.md"));
+        assert_eq!(imported[0].text, "ser:: to be\n");
+        assert_eq!(imported[0].cards[0].fsrs_state.review_log.len(), 1);
+        assert_eq!(
+            imported[0].cards[0].fsrs_state.review_date,
+            deck.cards
+                .iter()
+                .find(|x| x.content.front == "ser")
+                .unwrap()
+                .fsrs_state
+                .review_date
+        );
+        assert_eq!(imported[1].text, ":: {{{Madrid}}} is in {{{Spain}}}\n");
+        assert_eq!(imported[1].cards.len(), 2);
+    }
+}
@@ -1,6 +1,8 @@
 use crate::date::Date;
 use crate::fsrs::{FSRSState, ReviewLogItem};
+use crate::obsidian::split_sr_comment;
 use crate::parsing::{ClozeIterator, ClozeType};
+use crate::tags::strip_tags;
 use serde::{Deserialize, Serialize};
 use std::hash::{Hash, Hasher};
 use std::string::String;
@@ -121,7 +123,7 @@ impl Card {
             .next()
             .ok_or(String::from("missing review_date"))?
             .parse()?;
-        card.fsrs_state.buried = false;
+        card.fsrs_state.suspended = false;
         card.fsrs_state.review_date = current_date
             .checked_add_days(days_to_review)
             .ok_or(String::from("invalid review day"))?;
@@ -184,6 +186,187 @@ pub struct CardContent {
     pub base: Option<usize>,
     #[serde(default)]
     pub cloze_index: Option<usize>,
+    /// Schedules read from the comment after the card, or to be written there, one for each cloze
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub inline_schedules: Vec<InlineSchedule>,
+    /// Stable id written after the card, matched before the key when the deck is updated
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<CardId>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// Name of the preset set in the front matter of the file, used before the preset patterns
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preset: Option<String>,
+    /// Whether a reversed card is created as well, only set on the card parsed from the markdown
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub reverse: bool,
+}
+
+const BLOCK_ID_START: &str = "^tm-";
+const ID_COMMENT_START: &str = "<!--tmemo-id:";
+
+/// Id of a card, written as an Obsidian block id such as ^tm-k3x9a1 or inside a comment
+#[derive(Debug, Deserialize, Clone, PartialEq, Serialize)]
+pub enum CardId {
+    Block(String),
+    Comment(String),
+}
+
+impl CardId {
+    pub fn value(&self) -> &str {
+        match self {
+            CardId::Block(value) | CardId::Comment(value) => value,
+        }
+    }
+
+    /// Id of the same kind with another value, cloze cards get the id of the base card and the cloze index
+    pub fn with_value(&self, value: String) -> CardId {
+        match self {
+            CardId::Block(_) => CardId::Block(value),
+            CardId::Comment(_) => CardId::Comment(value),
+        }
+    }
+
+    pub fn marker(&self) -> String {
+        match self {
+            CardId::Block(value) => format!("^{}", value),
+            CardId::Comment(value) => format!("{}{}-->", ID_COMMENT_START, value),
+        }
+    }
+
+    fn is_valid(value: &str) -> bool {
+        value.starts_with("tm-")
+            && value.len() > 3
+            && value.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+    }
+
+    /// Splits an id from the end of the line, returning the rest of the line and the id
+    pub fn split(line: &str) -> (&str, Option<CardId>) {
+        let trimmed = line.trim_end();
+        if let Some(start) = trimmed.rfind(BLOCK_ID_START) {
+            let value = &trimmed[start + 1..];
+            let separated = start == 0 || trimmed[..start].ends_with(char::is_whitespace);
+            if separated && CardId::is_valid(value) {
+                return (
+                    trimmed[..start].trim_end(),
+                    Some(CardId::Block(value.to_string())),
+                );
+            }
+        }
+        if let Some(start) = trimmed.rfind(ID_COMMENT_START) {
+            let value = trimmed[start + ID_COMMENT_START.len()..].strip_suffix("-->");
+            if let Some(value) = value.filter(|x| CardId::is_valid(x)) {
+                return (
+                    trimmed[..start].trim_end(),
+                    Some(CardId::Comment(value.to_string())),
+                );
+            }
+        }
+        (line, None)
+    }
+}
+
+/// Splits the id and the schedule comment from the end of a card line, comments of the Obsidian plugin are dropped
+pub fn split_markers(line: &str) -> (&str, Option<Vec<InlineSchedule>>, Option<CardId>) {
+    let (text, id) = CardId::split(split_sr_comment(line));
+    let (text, schedules) = InlineSchedule::split_comment(split_sr_comment(text));
+    (text, schedules, id)
+}
+
+const INLINE_SCHEDULE_START: &str = "<!--tmemo:";
+const INLINE_SCHEDULE_END: &str = "-->";
+
+/// Due date and memory state stored in the markdown after a card
+#[derive(Debug, Deserialize, Clone, PartialEq, Serialize)]
+pub struct InlineSchedule {
+    pub due: Date,
+    pub stability: f64,
+    pub difficulty: f64,
+    /// Missing in comments written by older versions
+    #[serde(default)]
+    pub last_review: Option<Date>,
+}
+
+impl InlineSchedule {
+    pub fn from_state(state: &FSRSState) -> InlineSchedule {
+        InlineSchedule {
+            due: state.review_date,
+            stability: state.stability,
+            difficulty: state.difficulty,
+            last_review: Some(state.last_review),
+        }
+    }
+
+    /// New cards are stored with zero stability and left as they are
+    pub fn apply(&self, state: &mut FSRSState) {
+        if self.stability <= 0.0 {
+            return;
+        }
+        state.stability = self.stability;
+        state.difficulty = self.difficulty;
+        state.review_date = self.due;
+        // Older comments only have the due date, the interval is close to the stability at the default retention
+        state.last_review = self.last_review.unwrap_or_else(|| {
+            self.due
+                .checked_add_days(-(self.stability.round().max(1.0) as i32))
+                .unwrap()
+        });
+        state.complete_history = false;
+    }
+
+    /// Comment such as <!--tmemo:2024-03-01,12.5,5.2,2024-02-18--> with the due date, stability, difficulty and last review, the schedules of clozes are separated by semicolons
+    pub fn format_comment(schedules: &[InlineSchedule]) -> String {
+        let parts: Vec<String> = schedules
+            .iter()
+            .map(|x| {
+                let mut part = format!(
+                    "{},{:.4},{:.4}",
+                    x.due.to_naive().unwrap(),
+                    x.stability,
+                    x.difficulty
+                );
+                if let Some(last_review) = x.last_review {
+                    part.push_str(&format!(",{}", last_review.to_naive().unwrap()));
+                }
+                part
+            })
+            .collect();
+        format!(
+            "{}{}{}",
+            INLINE_SCHEDULE_START,
+            parts.join(";"),
+            INLINE_SCHEDULE_END
+        )
+    }
+
+    fn parse(text: &str) -> Option<InlineSchedule> {
+        let mut parts = text.split(',').map(|x| x.trim());
+        Some(InlineSchedule {
+            due: Date::parse(parts.next()?)?,
+            stability: parts.next()?.parse().ok()?,
+            difficulty: parts.next()?.parse().ok()?,
+            last_review: match parts.next() {
+                Some(part) => Some(Date::parse(part)?),
+                None => None,
+            },
+        })
+    }
+
+    /// Splits a schedule comment from the end of the line, returning the rest of the line and the schedules
+    pub fn split_comment(line: &str) -> (&str, Option<Vec<InlineSchedule>>) {
+        let start = match line.rfind(INLINE_SCHEDULE_START) {
+            Some(start) => start,
+            None => return (line, None),
+        };
+        let comment = line[start + INLINE_SCHEDULE_START.len()..].trim_end();
+        let schedules = comment
+            .strip_suffix(INLINE_SCHEDULE_END)
+            .and_then(|x| x.split(';').map(InlineSchedule::parse).collect());
+        match schedules {
+            Some(schedules) => (line[..start].trim_end(), Some(schedules)),
+            None => (line, None),
+        }
+    }
 }
 
 #[derive(Debug, Deserialize, Clone, Serialize)]
@@ -208,6 +391,11 @@ impl CardContent {
             editable: true,
             base: None,
             cloze_index: None,
+            inline_schedules: vec![],
+            id: None,
+            tags: vec![],
+            preset: None,
+            reverse: false,
         }
     }
 
@@ -221,10 +409,19 @@ impl CardContent {
         }
     }
 
+    /// Path of the file of the card relative to the working directory
     pub fn get_md_filename(&self) -> &str {
         self.prefix.split('>').next().unwrap().trim_end()
     }
 
+    /// Headings of the prefix without the file name
+    pub fn get_heading(&self) -> &str {
+        match self.prefix.split_once('>') {
+            Some((_, heading)) => heading.trim(),
+            None => "",
+        }
+    }
+
     pub fn to_string(&self) -> String {
         if self.front.find('\n').is_some() {
             format!(":::\n{}:::\n{}:::", self.front, self.back)
@@ -233,8 +430,29 @@ impl CardContent {
         }
     }
 
+    /// The card followed by the schedule comment and the id when there are any
+    pub fn to_markdown(&self) -> String {
+        let mut markers: Vec<String> = vec![];
+        if !self.inline_schedules.is_empty() {
+            markers.push(InlineSchedule::format_comment(&self.inline_schedules));
+        }
+        if let Some(id) = &self.id {
+            markers.push(id.marker());
+        }
+
+        let text = self.to_string();
+        if markers.is_empty() {
+            text
+        } else if self.front.find('\n').is_some() {
+            format!("{}\n{}", text, markers.join(" "))
+        } else {
+            format!("{} {}", text, markers.join(" "))
+        }
+    }
+
+    /// The front on one line without the tags
     pub fn get_singleline_front(&self) -> String {
-        self.front.replace("\n", "\\n")
+        strip_tags(&self.front).replace("\n", "\\n")
     }
 
     pub fn get_formatted_front(&self) -> String {
@@ -242,10 +460,16 @@ impl CardContent {
     }
 
     pub fn key(&self) -> String {
-        self.prefix.to_string() + &self.front
+        card_key(&self.prefix, &self.front)
     }
 }
 
+/// Tags are left out of the key so that tagging a card or its heading keeps its state,
+/// cards that only differ by their tags have the same key and are duplicates
+pub fn card_key(prefix: &str, front: &str) -> String {
+    strip_tags(prefix) + &strip_tags(front)
+}
+
 fn replace_cloze(input: &str, cloze_type: ClozeType) -> String {
     let mut iterator = ClozeIterator::new(cloze_type, input);
     let first = iterator.next();
@@ -287,8 +511,12 @@ impl CardCollection {
             cloze_front.push_str(&replace_cloze(cloze_item.after, cloze_type.clone()));
             let cloze_back = cloze_item.clozed.to_string();
 
+            let mut fsrs_state = FSRSState::new(card.fsrs_state.date_added);
+            if let Some(schedule) = card.content.inline_schedules.get(index) {
+                schedule.apply(&mut fsrs_state);
+            }
             let cloze_card = Card {
-                fsrs_state: FSRSState::new(card.fsrs_state.date_added),
+                fsrs_state,
                 content: CardContent {
                     prefix: card.content.prefix.to_string(),
                     front: cloze_front,
@@ -296,23 +524,81 @@ impl CardCollection {
                     editable: false, // Cloze cards are not editable
                     base: Some(self.base_cards.len()),
                     cloze_index: Some(index),
+                    inline_schedules: vec![],
+                    id: card
+                        .content
+                        .id
+                        .as_ref()
+                        .map(|id| id.with_value(format!("{}.{}", id.value(), index))),
+                    tags: card.content.tags.clone(),
+                    preset: card.content.preset.clone(),
+                    reverse: false,
                 },
             };
 
             self.cards.push(cloze_card);
         }
 
+        let mut card = card;
+        card.content.inline_schedules.clear();
         self.base_cards.push(card);
         Ok(())
     }
 
+    // The base card is split into the card and the card with the front and back swapped
+    fn create_reverse_cards(&mut self, card: Card) {
+        let sides = [
+            (&card.content.front, &card.content.back),
+            (&card.content.back, &card.content.front),
+        ];
+        for (index, (front, back)) in sides.into_iter().enumerate() {
+            let mut fsrs_state = FSRSState::new(card.fsrs_state.date_added);
+            if let Some(schedule) = card.content.inline_schedules.get(index) {
+                schedule.apply(&mut fsrs_state);
+            }
+            self.cards.push(Card {
+                fsrs_state,
+                content: CardContent {
+                    prefix: card.content.prefix.to_string(),
+                    front: front.to_string(),
+                    back: back.to_string(),
+                    editable: false,
+                    base: Some(self.base_cards.len()),
+                    cloze_index: Some(index),
+                    inline_schedules: vec![],
+                    id: card
+                        .content
+                        .id
+                        .as_ref()
+                        .map(|id| id.with_value(format!("{}.{}", id.value(), index))),
+                    tags: card.content.tags.clone(),
+                    preset: card.content.preset.clone(),
+                    reverse: false,
+                },
+            });
+        }
+
+        let mut card = card;
+        card.content.inline_schedules.clear();
+        self.base_cards.push(card);
+    }
+
     fn create_cards(&mut self, card: Card) -> Result<(), Box<dyn std::error::Error>> {
         let has_triple_braces =
             card.content.back.find("{{{").is_some() && card.content.back.find("}}}").is_some();
         let has_triple_paren =
             card.content.back.find("(((").is_some() && card.content.back.find(")))").is_some();
 
+        if !has_triple_paren && !has_triple_braces && card.content.reverse {
+            self.create_reverse_cards(card);
+            return Ok(());
+        }
         if !has_triple_paren && !has_triple_braces {
+            let mut card = card;
+            if let Some(schedule) = card.content.inline_schedules.first() {
+                schedule.apply(&mut card.fsrs_state);
+            }
+            card.content.inline_schedules.clear();
             self.cards.push(card);
             return Ok(());
         }
@@ -350,8 +636,9 @@ impl Eq for CardContent {}
 
 #[cfg(test)]
 mod tests {
-    use super::{Card, CardCollection, CardContent};
+    use super::{Card, CardCollection, CardContent, InlineSchedule};
     use crate::date::Date;
+    use crate::fsrs::{FSRSParams, ReviewAnswer};
     use crate::{fsrs::FSRSState, parsing::parse_cards};
 
     fn default_date() -> Date {
@@ -370,6 +657,11 @@ mod tests {
                 editable: false,
                 base: None,
                 cloze_index: None,
+                inline_schedules: vec![],
+                id: None,
+                tags: vec![],
+                preset: None,
+                reverse: false,
             },
         }];
 
@@ -394,6 +686,11 @@ mod tests {
                     editable: false,
                     base: None,
                     cloze_index: None,
+                    inline_schedules: vec![],
+                    id: None,
+                    tags: vec![],
+                    preset: None,
+                    reverse: false,
                 },
             },
             Card {
@@ -405,6 +702,11 @@ mod tests {
                     editable: false,
                     base: None,
                     cloze_index: None,
+                    inline_schedules: vec![],
+                    id: None,
+                    tags: vec![],
+                    preset: None,
+                    reverse: false,
                 },
             },
         ];
@@ -433,6 +735,11 @@ mod tests {
                 editable: false,
                 base: None,
                 cloze_index: None,
+                inline_schedules: vec![],
+                id: None,
+                tags: vec![],
+                preset: None,
+                reverse: false,
             },
         }];
 
@@ -447,6 +754,55 @@ mod tests {
         assert_eq!(collection.cards[1].content.base.unwrap(), 0);
     }
 
+    #[test]
+    fn inline_schedules_are_applied() {
+        let date = Date::from_ymd_opt(2024, 1, 1).unwrap();
+        let input = ":: {{{a}}} and {{{b}}} <!--tmemo:2024-03-01,12.0000,5.0000;2024-03-05,0.0000,0.0000-->\n";
+        let cards = parse_cards(input, date, None);
+        let collection = CardCollection::from(cards).unwrap();
+        assert_eq!(collection.cards.len(), 2);
+        assert!(collection.base_cards[0].content.inline_schedules.is_empty());
+        let first = &collection.cards[0].fsrs_state;
+        assert_eq!(first.stability, 12.0);
+        assert_eq!(first.review_date, Date::from_ymd_opt(2024, 3, 1).unwrap());
+        assert_eq!(first.last_review, Date::from_ymd_opt(2024, 2, 18).unwrap());
+        assert!(collection.cards[1].fsrs_state.is_new());
+
+        // The last review is kept when it is in the comment, such as for presets with another retention
+        let mut state = FSRSState::new(date);
+        state.review(ReviewAnswer::Good, &date, true, 1.0, &FSRSParams::new());
+        state.review_date = Date::from_ymd_opt(2024, 1, 20).unwrap();
+        let comment = InlineSchedule::format_comment(&[InlineSchedule::from_state(&state)]);
+        let (_, schedules) = InlineSchedule::split_comment(&comment);
+        let mut applied = FSRSState::new(date);
+        schedules.unwrap()[0].apply(&mut applied);
+        assert_eq!(applied.last_review, date);
+        assert_eq!(applied.review_date, state.review_date);
+    }
+
+    #[test]
+    fn reverse_cards_are_created() {
+        let date = Date::from_ymd_opt(2024, 1, 1).unwrap();
+        let input = "---\ntmemo-reverse: true\n---\ncasa:: house <!--tmemo:2024-03-01,12.0000,5.0000--> ^tm-abc\n";
+        let cards = parse_cards(input, date, None);
+        let collection = CardCollection::from(cards).unwrap();
+        assert_eq!(collection.base_cards.len(), 1);
+        assert_eq!(collection.cards.len(), 2);
+        let (forward, reversed) = (&collection.cards[0].content, &collection.cards[1].content);
+        assert_eq!(
+            (forward.front.as_str(), forward.back.as_str()),
+            ("casa", "house")
+        );
+        assert_eq!(
+            (reversed.front.as_str(), reversed.back.as_str()),
+            ("house", "casa")
+        );
+        assert_eq!(reversed.cloze_index, Some(1));
+        assert_eq!(reversed.id.as_ref().unwrap().value(), "tm-abc.1");
+        assert_eq!(collection.cards[0].fsrs_state.stability, 12.0);
+        assert!(collection.cards[1].fsrs_state.is_new());
+    }
+
     #[test]
     fn tsv_conversion_works() {
         let mut card = Card::new();
@@ -477,6 +833,11 @@ mod tests {
             editable: true,
             base: None,
             cloze_index: None,
+            inline_schedules: vec![],
+            id: None,
+            tags: vec![],
+            preset: None,
+            reverse: false,
         };
 
         let card_content2 = CardContent {
@@ -486,6 +847,11 @@ mod tests {
             editable: true,
             base: None,
             cloze_index: None,
+            inline_schedules: vec![],
+            id: None,
+            tags: vec![],
+            preset: None,
+            reverse: false,
         };
 
         assert_eq!(card_content.get_md_filename(), "test.md");
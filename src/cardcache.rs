@@ -10,14 +10,19 @@ use std::ffi::{OsStr, OsString};
 use std::fmt;
 use std::fs;
 use std::io::{BufReader, BufWriter};
+use std::path::Path;
 use std::time::SystemTime;
 
-pub const PARSING_VERSION: u32 = 3;
+pub const PARSING_VERSION: u32 = 5;
+/// Changes when the cards parsed from the same file change, the cache is then rebuilt without migrating the deck
+const CACHE_VERSION: u32 = 4;
 
 #[derive(Deserialize, Serialize)]
 pub struct CardCache {
     #[serde(default)]
     parsing_version: u32,
+    #[serde(default)]
+    cache_version: u32,
     timestamp_cache: HashMap<String, SystemTime>,
     card_cache: HashMap<String, Vec<Card>>,
     #[serde(skip_serializing, skip_deserializing)]
@@ -28,15 +33,22 @@ pub struct File {
     pub path: OsString,
     pub string_path: String,
     pub string_filename: String,
+    /// Path from the searched directory with / as the separator, used as the first part of the card prefixes
+    pub relative_path: String,
     pub metadata: fs::Metadata,
 }
 
 pub fn get_md_files_in_path(path: &OsStr) -> Vec<File> {
     let mut result: Vec<File> = vec![];
+    collect_md_files(Path::new(path), path, &mut result);
+    result
+}
+
+fn collect_md_files(root: &Path, path: &OsStr, result: &mut Vec<File>) {
     let it_result = fs::read_dir(path);
 
     if it_result.is_err() {
-        return vec![];
+        return;
     }
 
     for entry_result in it_result.unwrap() {
@@ -51,21 +63,27 @@ pub fn get_md_files_in_path(path: &OsStr) -> Vec<File> {
             continue; // skip hidden directories and files
         }
 
+        let relative_path: Vec<String> = entry
+            .path()
+            .strip_prefix(root)
+            .unwrap()
+            .components()
+            .map(|x| x.as_os_str().to_string_lossy().to_string())
+            .collect();
         let file = File {
             path: entry.path().into_os_string(),
             string_path: entry.path().to_string_lossy().to_string(),
             string_filename: str_filename.to_string(),
+            relative_path: relative_path.join("/"),
             metadata: entry.metadata().unwrap(),
         };
 
         if file.metadata.is_dir() {
-            result.extend(get_md_files_in_path(&file.path));
+            collect_md_files(root, &file.path, result);
         } else if str_filename.ends_with(".md") {
             result.push(file);
         }
     }
-
-    result
 }
 
 #[derive(Debug)]
@@ -102,6 +120,7 @@ impl CardCache {
                 card_cache: HashMap::new(),
                 changed: false,
                 parsing_version: PARSING_VERSION,
+                cache_version: CACHE_VERSION,
             },
         }
     }
@@ -111,7 +130,11 @@ impl CardCache {
         let reader = BufReader::new(file);
         let cache: CardCache = serde_json::from_reader(reader)?;
 
-        if cache.parsing_version == PARSING_VERSION {
+        if cache.cache_version != CACHE_VERSION {
+            Err(Box::new(SimpleError::new(
+                "Cache was made by another version",
+            )))
+        } else if cache.parsing_version == PARSING_VERSION {
             Ok(cache)
         } else if cache.parsing_version < PARSING_VERSION {
             Err(Box::new(SimpleError::new(
@@ -168,7 +191,7 @@ impl CardCache {
 
             if self.has_changed_and_update(&entry.string_path, &metadata) {
                 let contents = parsing::read_to_string(&entry.path);
-                let heading = entry.string_filename;
+                let heading = entry.relative_path;
                 path_cards = parsing::parse_cards(&contents, date, Some(heading));
                 self.card_cache
                     .insert(entry.string_path, path_cards.clone());
@@ -202,6 +225,11 @@ mod tests {
         let cards = cache
             .get_all_cards_in_work_directory(Some(date(2024, 1, 1)))
             .unwrap();
-        assert_eq!(cards.cards.len(), 11);
+        // The examples in the code blocks of the readme are not cards
+        assert_eq!(cards.cards.len(), 9);
+        assert!(cards
+            .cards
+            .iter()
+            .any(|x| x.content.get_md_filename() == "test/test_cards2.md"));
     }
 }
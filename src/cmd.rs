@@ -1,8 +1,15 @@
+use crate::anki;
+use crate::card::Card;
 use crate::date::Date;
-use crate::deck::Deck;
+use crate::deck::{CardIds, Deck};
 use crate::migrations;
+use crate::obsidian;
+use crate::optimizer::{self, OptimizerSettings};
 use crate::rand::SplitMix64;
+use crate::simulation::{self, WorkloadSettings};
+use crate::stats;
 use crate::{cardcache::CardCache, fsrs::ReviewAnswer};
+use std::path::Path;
 use std::time::{SystemTime, UNIX_EPOCH};
 
 pub struct Cli {
@@ -11,6 +18,38 @@ pub struct Cli {
     pub state_from_file: Option<String>,
 }
 
+/// Overrides of the settings derived from the deck for the simulate command
+pub struct SimulateOptions {
+    pub days: usize,
+    pub new_cards_per_day: Option<usize>,
+    pub retention: Option<f64>,
+    pub new_card_limit: Option<u32>,
+    pub review_limit: Option<u32>,
+    pub first_answers: Option<Vec<f64>>,
+    pub answers: Option<Vec<f64>>,
+    pub costs: Option<Vec<f64>>,
+    pub json: bool,
+}
+
+fn parse_arg<T: std::str::FromStr>(arg: Option<String>, help_text: &str) -> T {
+    match arg.map(|x| x.parse()) {
+        Some(Ok(value)) => value,
+        _ => panic!("{}", help_text),
+    }
+}
+
+fn parse_floats(arg: Option<String>, count: usize, help_text: &str) -> Vec<f64> {
+    let values: Vec<f64> = arg
+        .expect(help_text)
+        .split(',')
+        .map(|x| x.trim().parse().expect(help_text))
+        .collect();
+    if values.len() != count {
+        panic!("{}", help_text);
+    }
+    values
+}
+
 pub enum Command {
     Init,
     Print,
@@ -23,44 +62,25 @@ pub enum Command {
     ExportReviewLogs,
     Accuracy,
     Find(String),
-    SimulateReview(usize),
+    Simulate(SimulateOptions),
     Migrate,
-}
-
-struct ReviewData {
-    pub cards: usize,
-}
-
-fn simulate_review(mut deck: Deck, days: usize) -> Vec<ReviewData> {
-    let mut output = Vec::with_capacity(days);
-    let current_day = Date::now();
-    let mut rng = SplitMix64::from_seed(0);
-
-    for i in 0..days {
-        let review_day = current_day.checked_add_days(i as i32).unwrap();
-        deck.start_review(review_day, &mut rng);
-        output.push(ReviewData {
-            cards: deck.review_indices.len(),
-        });
-
-        while let Some(card) = deck.get_review_card() {
-            let ret = card.fsrs_state.retention(&review_day);
-            let rng_result = rng.next_float(0.0, 1.0);
-            let answer = if rng_result < ret {
-                ReviewAnswer::Good
-            } else {
-                ReviewAnswer::Again
-            };
-
-            deck.review_card(answer, &mut rng);
-        }
-    }
-
-    output
+    UpgradeFsrs,
+    Optimize(Option<String>),
+    OptimalRetention(usize, bool),
+    Leeches,
+    Heatmap(usize),
+    ImportAnki(String, String),
+    ExportAnki(String, bool),
+    ImportObsidianSr(bool),
+    InlineSchedule(bool),
+    CardIds(CardIds),
+    Suspended,
+    Suspend(String, Option<Date>),
+    Unsuspend(String),
 }
 
 impl Cli {
-    pub fn parse(mut args: std::env::Args) -> Cli {
+    pub fn parse(args: std::env::Args) -> Cli {
         if args.len() <= 1 {
             return Cli {
                 command: None,
@@ -71,7 +91,12 @@ impl Cli {
 
         let random_schedule_help_text = "usage: tmemo schedule-random [fraction], e.g. 0.1 to generate reviews between 0.9 and 1.1";
         let schedule_help_text = "usage: tmemo schedule <days> [max cards per day]";
+        let suspend_help_text = "usage: tmemo suspend <search> [YYYY-MM-DD]";
+        let simulate_help_text = "usage: tmemo simulate <days> [--new N] [--retention R] [--new-limit N] [--review-limit N] [--first-answers again,hard,good,easy] [--answers hard,good,easy] [--costs new,again,hard,good,easy] [--json]";
+        let import_anki_help_text = "usage: tmemo import-anki <file.apkg> [folder]";
+        let optimal_retention_help_text = "usage: tmemo optimal-retention [days] [write]";
 
+        let mut args = args.peekable();
         args.next();
 
         let mut cli = Cli {
@@ -91,6 +116,66 @@ impl Cli {
                 "update" => Some(Command::Update),
                 "review-log" => Some(Command::ExportReviewLogs),
                 "migrate" => Some(Command::Migrate),
+                "upgrade-fsrs" => Some(Command::UpgradeFsrs),
+                "leeches" => Some(Command::Leeches),
+                "heatmap" => {
+                    let weeks: usize = match args.next() {
+                        None => 52,
+                        Some(weeks) => weeks.parse().expect("usage: tmemo heatmap [weeks]"),
+                    };
+                    Some(Command::Heatmap(weeks))
+                }
+                "import-anki" => {
+                    let path = args.next().expect(import_anki_help_text);
+                    let folder = args.next().unwrap_or("anki".to_string());
+                    Some(Command::ImportAnki(path, folder))
+                }
+                "export-anki" => {
+                    let path = args
+                        .next_if(|x| x != "schedule")
+                        .unwrap_or("tmemo.apkg".to_string());
+                    let schedule = args.next_if(|x| x == "schedule").is_some();
+                    Some(Command::ExportAnki(path, schedule))
+                }
+                "import-obsidian-sr" => {
+                    let strip = args.next_if(|x| x == "strip").is_some();
+                    Some(Command::ImportObsidianSr(strip))
+                }
+                "card-ids" => {
+                    let card_ids = match args.next().as_deref() {
+                        Some("block") => CardIds::Block,
+                        Some("comment") => CardIds::Comment,
+                        Some("off") => CardIds::Off,
+                        _ => panic!("usage: tmemo card-ids <block|comment|off>"),
+                    };
+                    Some(Command::CardIds(card_ids))
+                }
+                "inline-schedule" => {
+                    let off = args.next_if(|x| x == "off").is_some();
+                    Some(Command::InlineSchedule(!off))
+                }
+                "suspended" => Some(Command::Suspended),
+                "suspend" => {
+                    let search_string = args.next().expect(suspend_help_text);
+                    let until = args.next_if(|x| Date::parse(x).is_some());
+                    Some(Command::Suspend(
+                        search_string,
+                        until.and_then(|x| Date::parse(&x)),
+                    ))
+                }
+                "unsuspend" => {
+                    let search_string = args
+                        .next()
+                        .expect("usage: tmemo unsuspend <search>, use \"\" for all cards");
+                    Some(Command::Unsuspend(search_string))
+                }
+                "optimize" => Some(Command::Optimize(None)),
+                "optimize-preset" => {
+                    let name = args
+                        .next()
+                        .expect("Expected preset name after optimize-preset");
+                    Some(Command::Optimize(Some(name)))
+                }
                 "schedule-random" => {
                     let fraction: f64 = match args.next() {
                         None => 0.1,
@@ -120,12 +205,60 @@ impl Cli {
                     Some(Command::Find(search_string))
                 }
                 "simulate" => {
-                    let days: usize = args
-                        .next()
-                        .expect("number of days expected after simulate")
-                        .parse()
-                        .expect("expected valid unsigned integer number of days");
-                    Some(Command::SimulateReview(days))
+                    let days = parse_arg(args.next(), simulate_help_text);
+                    let mut options = SimulateOptions {
+                        days,
+                        new_cards_per_day: None,
+                        retention: None,
+                        new_card_limit: None,
+                        review_limit: None,
+                        first_answers: None,
+                        answers: None,
+                        costs: None,
+                        json: false,
+                    };
+                    while let Some(flag) = args.next_if(|x| x.starts_with("--")) {
+                        match flag.as_str() {
+                            "--new" => {
+                                options.new_cards_per_day =
+                                    Some(parse_arg(args.next(), simulate_help_text))
+                            }
+                            "--retention" => {
+                                options.retention = Some(parse_arg(args.next(), simulate_help_text))
+                            }
+                            "--new-limit" => {
+                                options.new_card_limit =
+                                    Some(parse_arg(args.next(), simulate_help_text))
+                            }
+                            "--review-limit" => {
+                                options.review_limit =
+                                    Some(parse_arg(args.next(), simulate_help_text))
+                            }
+                            "--first-answers" => {
+                                options.first_answers =
+                                    Some(parse_floats(args.next(), 4, simulate_help_text))
+                            }
+                            "--answers" => {
+                                options.answers =
+                                    Some(parse_floats(args.next(), 3, simulate_help_text))
+                            }
+                            "--costs" => {
+                                options.costs =
+                                    Some(parse_floats(args.next(), 5, simulate_help_text))
+                            }
+                            "--json" => options.json = true,
+                            _ => panic!("{}", simulate_help_text),
+                        }
+                    }
+                    Some(Command::Simulate(options))
+                }
+                "optimal-retention" => {
+                    let days: usize = match args.next_if(|x| x.parse::<usize>().is_ok()) {
+                        None => 365,
+                        Some(days) => days.parse().expect(optimal_retention_help_text),
+                    };
+                    let write = args.next_if(|x| x == "write").is_some();
+                    Some(Command::OptimalRetention(days, write))
                 }
                 "-s" => {
                     cli.from_stdin = true;
@@ -161,7 +294,7 @@ impl Cli {
                 }
                 let mut deck = Deck::new();
                 deck.save_to_file().unwrap();
-            },
+            }
             Command::Print => {
                 let deck = result.unwrap();
                 deck.print_card_data();
@@ -204,6 +337,15 @@ impl Cli {
                 deck.replace_cards(cards).unwrap();
                 deck.save_to_file().unwrap();
                 println!("Deck updated");
+                for (from, to) in &deck.moved_files {
+                    println!("Moved the cards of {} to {}", from, to);
+                }
+                if !deck.relink_proposals.is_empty() {
+                    println!(
+                        "{} orphans are similar to new cards, relink them under Relink orphans in the TUI",
+                        deck.relink_proposals.len()
+                    );
+                }
             }
             Command::Schedule(days, max_cards) => {
                 let mut deck = result.unwrap();
@@ -239,7 +381,9 @@ impl Cli {
                     let mut previous_timestamp: u64 = 0;
                     for review_index in 0..review_log.len() {
                         let timestamp: u64;
-                        if review_index == 0
+                        if let Some(time) = review_log[review_index].timestamp {
+                            timestamp = time as u64;
+                        } else if review_index == 0
                             || review_log[review_index - 1].day != review_log[review_index].day
                         {
                             // Set the timestamp to be in noon
@@ -249,11 +393,15 @@ impl Cli {
                                     * 60
                                     * 1000;
                         } else {
-                            // exact time of reviews is not kept, only day so add 10 seconds for the timestamp
+                            // exact time of reviews was not kept before, only day so add 10 seconds for the timestamp
                             timestamp = previous_timestamp + 10000;
                         }
+                        let duration = match review_log[review_index].duration {
+                            Some(duration) => duration.to_string(),
+                            None => String::new(),
+                        };
                         println!(
-                            "{card_index},{timestamp},{},,",
+                            "{card_index},{timestamp},{},,{duration}",
                             match review_log[review_index].answer {
                                 ReviewAnswer::Again => 1,
                                 ReviewAnswer::Hard => 2,
@@ -287,16 +435,263 @@ impl Cli {
                     println!("{}", card.format_to_tsv(Date::now()));
                 }
             }
-            Command::SimulateReview(days) => {
+            Command::Simulate(options) => {
                 let deck = result.unwrap();
-                let sim = simulate_review(deck, *days);
-                for (index, data) in sim.into_iter().enumerate() {
-                    println!("{} {}", index, data.cards);
+                let mut settings = WorkloadSettings::from_deck(&deck, options.days);
+                if let Some(new_cards) = options.new_cards_per_day {
+                    settings.new_cards_per_day = new_cards;
+                }
+                if options.new_card_limit.is_some() {
+                    settings.new_card_limit = options.new_card_limit;
+                }
+                if options.review_limit.is_some() {
+                    settings.review_limit = options.review_limit;
+                }
+                if let Some(first) = &options.first_answers {
+                    settings.answers.first.copy_from_slice(first);
+                }
+                if let Some(answers) = &options.answers {
+                    settings.answers.recalled.copy_from_slice(answers);
+                }
+                if let Some(costs) = &options.costs {
+                    settings.costs.new_card = costs[0];
+                    settings.costs.again = costs[1];
+                    settings.costs.hard = costs[2];
+                    settings.costs.good = costs[3];
+                    settings.costs.easy = costs[4];
+                }
+
+                let sim = simulation::simulate(&deck, &settings, options.retention);
+                if options.json {
+                    println!("{}", serde_json::to_string_pretty(&sim.days).unwrap());
+                } else {
+                    println!("day,due,reviews,new_cards,minutes,retention,learned");
+                    for day in &sim.days {
+                        println!(
+                            "{},{},{},{},{:.1},{:.4},{}",
+                            day.day,
+                            day.due,
+                            day.reviews,
+                            day.new_cards,
+                            day.minutes,
+                            day.retention,
+                            day.learned
+                        );
+                    }
+                }
+            }
+            Command::OptimalRetention(days, write) => {
+                let mut deck = result.unwrap();
+                let settings = WorkloadSettings::from_deck(&deck, *days);
+                let results = simulation::optimal_retention(&deck, &settings);
+                println!("Retention\tMinutes\tRemembered\tSeconds per remembered card");
+                for result in &results {
+                    println!(
+                        "{:.2}\t{:.0}\t{:.1}\t{:.2}",
+                        result.target_retention,
+                        result.time / 60.0,
+                        result.remembered,
+                        result.time_per_remembered()
+                    );
+                }
+                let best = simulation::best_retention(&results)
+                    .unwrap()
+                    .target_retention;
+                println!("Optimal retention is {:.2}", best);
+                if *write {
+                    deck.params.target_retention = best;
+                    deck.save_to_file().unwrap();
+                    println!("Target retention of the deck set to {:.2}", best);
                 }
             }
+            Command::Heatmap(weeks) => {
+                let deck = result.unwrap();
+                let date = Date::now();
+                let reviews = stats::reviews_by_day(&deck);
+                for line in stats::heatmap_lines(&reviews, date, *weeks) {
+                    println!("{}", line);
+                }
+                let streaks = stats::streaks(&reviews, date);
+                println!(
+                    "Current streak {} days, longest streak {} days",
+                    streaks.current, streaks.longest
+                );
+            }
+            Command::ImportAnki(path, folder) => {
+                let mut deck = result.unwrap();
+                let summary =
+                    anki::import_apkg(Path::new(path), Path::new(folder), &mut deck).unwrap();
+                deck.save_to_file().unwrap();
+                println!(
+                    "Imported {} notes as {} cards into {}",
+                    summary.notes, summary.cards, folder
+                );
+                if summary.skipped > 0 {
+                    println!(
+                        "{} cards of other card types such as reversed cards were skipped",
+                        summary.skipped
+                    );
+                }
+                if summary.existing > 0 {
+                    println!(
+                        "{} notes already in the deck were skipped",
+                        summary.existing
+                    );
+                }
+            }
+            Command::ExportAnki(path, schedule) => {
+                let mut deck = result.unwrap();
+                // Cloze notes are exported from their base cards which are only available after parsing
+                let mut cache = CardCache::new();
+                let cards = cache.get_all_cards_in_work_directory(None).unwrap();
+                deck.replace_cards(cards).unwrap();
+                let notes = anki::collect_notes(&deck);
+                if path.ends_with(".txt") {
+                    std::fs::write(path, anki::notes_to_text(&notes)).unwrap();
+                } else {
+                    anki::export_apkg(&notes, Path::new(path), *schedule).unwrap();
+                }
+                println!("Exported {} notes into {}", notes.len(), path);
+            }
+            Command::ImportObsidianSr(strip) => {
+                let mut deck = result.unwrap();
+                let mut cache = CardCache::new();
+                let cards = cache.get_all_cards_in_work_directory(None).unwrap();
+                deck.replace_cards(cards).unwrap();
+                let count = obsidian::import_sr(&mut deck).unwrap();
+                println!("Scheduling imported for {} cards", count);
+                let strip = *strip || {
+                    println!("Remove the scheduling comments from the files? [y/N]");
+                    let mut answer = String::new();
+                    std::io::stdin().read_line(&mut answer).unwrap();
+                    answer.trim().eq_ignore_ascii_case("y")
+                };
+                if strip {
+                    obsidian::strip_sr_files().unwrap();
+                    println!("Scheduling comments removed");
+                }
+                deck.save_to_file().unwrap();
+            }
+            Command::CardIds(card_ids) => {
+                let mut deck = result.unwrap();
+                deck.card_ids = card_ids.clone();
+                let mut cache = CardCache::new();
+                let cards = cache.get_all_cards_in_work_directory(None).unwrap();
+                deck.replace_cards(cards).unwrap();
+                deck.save_to_file().unwrap();
+            }
+            Command::InlineSchedule(enabled) => {
+                let mut deck = result.unwrap();
+                let mut cache = CardCache::new();
+                let cards = cache.get_all_cards_in_work_directory(None).unwrap();
+                deck.replace_cards(cards).unwrap();
+                deck.set_inline_schedule(*enabled);
+                deck.save_to_file().unwrap();
+            }
+            Command::Leeches => {
+                let deck = result.unwrap();
+                for index in deck.leech_indices() {
+                    let card = &deck.cards[index];
+                    println!(
+                        "{}\t{}\t{}\t{}",
+                        card.content.get_md_filename(),
+                        card.content.get_heading(),
+                        card.fsrs_state.lapses(),
+                        card.content.get_singleline_front()
+                    );
+                }
+            }
+            Command::Suspended => {
+                let deck = result.unwrap();
+                for index in deck.suspended_indices(Date::now()) {
+                    let card = &deck.cards[index];
+                    let state = &card.fsrs_state;
+                    let until = match (state.suspended, state.suspended_until, state.buried_until) {
+                        (true, Some(until), _) => {
+                            format!("suspended until {}", until.to_naive().unwrap())
+                        }
+                        (true, None, _) => "suspended".to_owned(),
+                        (false, _, Some(until)) => {
+                            format!("buried until {}", until.to_naive().unwrap())
+                        }
+                        (false, _, None) => String::new(),
+                    };
+                    println!(
+                        "{}\t{}\t{}\t{}",
+                        card.content.get_md_filename(),
+                        card.content.get_heading(),
+                        until,
+                        card.content.get_singleline_front()
+                    );
+                }
+            }
+            Command::Suspend(search_string, until) => {
+                let mut deck = result.unwrap();
+                let indices = deck.find_cards(search_string.clone());
+                for index in &indices {
+                    deck.cards[*index].fsrs_state.suspend(*until);
+                }
+                deck.save_to_file().unwrap();
+                println!("{} cards suspended", indices.len());
+            }
+            Command::Unsuspend(search_string) => {
+                let mut deck = result.unwrap();
+                let suspended = deck.suspended_indices(Date::now());
+                let indices: Vec<usize> = deck
+                    .find_cards(search_string.clone())
+                    .into_iter()
+                    .filter(|index| suspended.contains(index))
+                    .collect();
+                for index in &indices {
+                    deck.cards[*index].fsrs_state.unsuspend();
+                }
+                deck.save_to_file().unwrap();
+                println!("{} cards unsuspended", indices.len());
+            }
             Command::Migrate => {
                 migrations::migrate_deck("tmemodeck.json".into()).unwrap();
             }
+            Command::UpgradeFsrs => match migrations::upgrade_fsrs("tmemodeck.json".into()) {
+                Ok(()) => println!("Upgraded the deck to FSRS-6"),
+                Err(err) => println!("{}", err),
+            },
+            Command::Optimize(preset_name) => {
+                let mut deck = result.unwrap();
+                let settings = OptimizerSettings::default();
+                // Only fit the cards that are scheduled with the parameters being optimized
+                let preset_index = match preset_name {
+                    Some(name) => match deck.presets.iter().position(|x| &x.name == name) {
+                        Some(index) => Some(index),
+                        None => panic!("No preset named {}", name),
+                    },
+                    None => None,
+                };
+                let cards: Vec<Card> = deck
+                    .cards
+                    .iter()
+                    .filter(|card| deck.preset_index_for(card) == preset_index)
+                    .cloned()
+                    .collect();
+                let initial = match preset_index {
+                    Some(index) => &deck.presets[index].params,
+                    None => &deck.params,
+                };
+                match optimizer::optimize(&cards, initial, &settings) {
+                    Ok(optimized) => {
+                        println!(
+                            "Optimized with {} reviews, log loss {:.4} => {:.4}",
+                            optimized.review_count, optimized.initial_loss, optimized.final_loss
+                        );
+                        println!("{:?}", optimized.params.w);
+                        match preset_index {
+                            Some(index) => deck.presets[index].params = optimized.params,
+                            None => deck.params = optimized.params,
+                        }
+                        deck.save_to_file().unwrap();
+                    }
+                    Err(err) => println!("{}", err),
+                }
+            }
         }
     }
 }
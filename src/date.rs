@@ -1,4 +1,4 @@
-use chrono::{Datelike, Days, Local, NaiveDate, TimeDelta, Utc};
+use chrono::{DateTime, Datelike, Days, Local, NaiveDate, TimeDelta, Utc};
 use serde::{Deserialize, Serialize};
 
 #[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
@@ -12,12 +12,29 @@ impl Date {
     }
 
     pub fn now() -> Date {
+        Date::from_timestamp(current_timestamp())
+    }
+
+    /// Day of a UTC timestamp in milliseconds
+    pub fn from_timestamp(timestamp: i64) -> Date {
         // Day changes 4 hours from midnight
-        let time = Utc::now().checked_add_signed(TimeDelta::hours(-4)).unwrap();
+        let time = DateTime::<Utc>::from_timestamp_millis(timestamp)
+            .unwrap()
+            .checked_add_signed(TimeDelta::hours(-4))
+            .unwrap();
         let dt = time.with_timezone(&Local);
         Date::from_naive(dt.date_naive())
     }
 
+    /// UTC timestamp in milliseconds of the local noon of the day
+    pub fn noon_timestamp(&self) -> i64 {
+        let noon = self.to_naive().unwrap().and_hms_opt(12, 0, 0).unwrap();
+        match noon.and_local_timezone(Local).earliest() {
+            Some(time) => time.timestamp_millis(),
+            None => noon.and_utc().timestamp_millis(),
+        }
+    }
+
     pub fn from_naive(date: NaiveDate) -> Date {
         Date {
             day: date.num_days_from_ce() - 1,
@@ -43,12 +60,23 @@ impl Date {
         Some(Date::from_naive(naive))
     }
 
+    /// Parses a date in YYYY-MM-DD format
+    pub fn parse(input: &str) -> Option<Date> {
+        let naive = NaiveDate::parse_from_str(input, "%Y-%m-%d").ok()?;
+        Some(Date::from_naive(naive))
+    }
+
     pub fn from_ymd_opt(year: i32, month: u32, day: u32) -> Option<Date> {
         let naive = NaiveDate::from_ymd_opt(year, month, day)?;
         Some(Date::from_naive(naive))
     }
 }
 
+/// Current UTC time in milliseconds since the unix epoch
+pub fn current_timestamp() -> i64 {
+    Utc::now().timestamp_millis()
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -60,4 +88,16 @@ mod tests {
         let back = converted.to_naive().unwrap();
         assert_eq!(orig, back);
     }
+
+    #[test]
+    fn parsing_works() {
+        assert_eq!(Date::parse("2024-02-29"), Date::from_ymd_opt(2024, 2, 29));
+        assert_eq!(Date::parse("2024-02-30"), None);
+    }
+
+    #[test]
+    fn timestamp_conversion_works() {
+        let date = Date::from_ymd_opt(2024, 3, 31).unwrap();
+        assert_eq!(Date::from_timestamp(date.noon_timestamp()), date);
+    }
 }
@@ -1,12 +1,15 @@
-use crate::card::{Card, CardCollection};
-use crate::date::Date;
-use crate::fsrs::{FSRSParams, ReviewAnswer, ReviewResult};
+use crate::card::{card_key, Card, CardCollection, CardId, InlineSchedule};
+use crate::date::{current_timestamp, Date};
+use crate::fsrs::{FSRSParams, FSRSState, LearningStep, ReviewAnswer, ReviewResult, ReviewTiming};
 use crate::parsing::try_replacing_cards;
-use crate::rand::SplitMix64;
+use crate::preset::Preset;
+use crate::rand::{fnv1a, SplitMix64};
+use crate::relink::{propose_relinks, Relink};
+use crate::tags::{merge_tags, tag_matches};
 use serde::{Deserialize, Serialize};
 use serde_json;
 use std::collections::BTreeMap;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fs;
 use std::io::{BufReader, BufWriter};
 use std::string::String;
@@ -19,6 +22,46 @@ pub struct Deck {
     pub track_review_history: bool,
     pub parsing_version: u32,
     pub params: FSRSParams,
+    /// Learning steps for new cards in minutes, cards graduate after the last one
+    #[serde(default)]
+    pub learning_steps: Vec<u32>,
+    /// Relearning steps for forgotten cards in minutes
+    #[serde(default)]
+    pub relearning_steps: Vec<u32>,
+    /// Parameters for groups of cards, the first matching preset is used and params otherwise
+    #[serde(default)]
+    pub presets: Vec<Preset>,
+    /// Number of lapses after which a card becomes a leech, 0 disables leech detection
+    #[serde(default = "default_leech_threshold")]
+    pub leech_threshold: u32,
+    #[serde(default)]
+    pub leech_action: LeechAction,
+    /// Whether cloze siblings of a reviewed card are deferred to later days
+    #[serde(default)]
+    pub bury_new_siblings: bool,
+    #[serde(default)]
+    pub bury_review_siblings: bool,
+    /// Daily limits, counting what was already studied today according to the review log
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub new_cards_per_day: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reviews_per_day: Option<u32>,
+    #[serde(default)]
+    pub review_order: ReviewOrder,
+    #[serde(default)]
+    pub new_card_position: NewCardPosition,
+    /// Reviews only include the cards with this tag or a tag nested under it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub review_tag: Option<String>,
+    /// Whether the schedules of reviewed cards are written into comments after the cards in the markdown
+    #[serde(default)]
+    pub inline_schedule: bool,
+    /// Whether new cards get an id written after them in the markdown
+    #[serde(default)]
+    pub card_ids: CardIds,
+    /// Orphans are relinked to new cards without asking when the similarity is at least this, above 1 always asks
+    #[serde(default = "default_relink_threshold")]
+    pub relink_threshold: f64,
 
     #[serde(skip_serializing, skip_deserializing)]
     pub review_index: Option<usize>,
@@ -30,13 +73,114 @@ pub struct Deck {
     pub review_indices: Vec<usize>,
     #[serde(skip_serializing, skip_deserializing)]
     pub review_date: Option<Date>,
+    #[serde(skip_serializing, skip_deserializing)]
+    pub scheduled_keys: Vec<String>,
+    #[serde(skip_serializing, skip_deserializing)]
+    pub relink_proposals: Vec<Relink>,
+    /// Orphan and card keys of the rejected pairings, which aren't proposed again
+    #[serde(default)]
+    pub rejected_relinks: Vec<(String, String)>,
+    /// Files detected as renamed or moved on the last update, from the old name to the new one
+    #[serde(skip_serializing, skip_deserializing)]
+    pub moved_files: Vec<(String, String)>,
+}
+
+/// What happens to a card when it becomes a leech
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub enum LeechAction {
+    #[default]
+    Flag,
+    Suspend,
+}
+
+/// How the ids of the cards are written into the markdown
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub enum CardIds {
+    #[default]
+    Off,
+    Block,
+    Comment,
+}
+
+/// Order in which the cards of a review are shown
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub enum ReviewOrder {
+    #[default]
+    Random,
+    LowestRetrievability,
+    MostOverdue,
+    DueDate,
+    GroupedByFile,
+    InterleavedByPrefix,
+}
+
+/// Whether new cards are shown mixed with the reviews or before or after them
+#[derive(Clone, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub enum NewCardPosition {
+    #[default]
+    Mixed,
+    First,
+    Last,
+}
+
+impl ReviewOrder {
+    pub fn next(&self) -> ReviewOrder {
+        match self {
+            ReviewOrder::Random => ReviewOrder::LowestRetrievability,
+            ReviewOrder::LowestRetrievability => ReviewOrder::MostOverdue,
+            ReviewOrder::MostOverdue => ReviewOrder::DueDate,
+            ReviewOrder::DueDate => ReviewOrder::GroupedByFile,
+            ReviewOrder::GroupedByFile => ReviewOrder::InterleavedByPrefix,
+            ReviewOrder::InterleavedByPrefix => ReviewOrder::Random,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ReviewOrder::Random => "random",
+            ReviewOrder::LowestRetrievability => "lowest retrievability first",
+            ReviewOrder::MostOverdue => "most overdue first",
+            ReviewOrder::DueDate => "due date",
+            ReviewOrder::GroupedByFile => "grouped by file and heading",
+            ReviewOrder::InterleavedByPrefix => "interleaved by prefix",
+        }
+    }
+}
+
+impl NewCardPosition {
+    pub fn next(&self) -> NewCardPosition {
+        match self {
+            NewCardPosition::Mixed => NewCardPosition::First,
+            NewCardPosition::First => NewCardPosition::Last,
+            NewCardPosition::Last => NewCardPosition::Mixed,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            NewCardPosition::Mixed => "mixed with reviews",
+            NewCardPosition::First => "before reviews",
+            NewCardPosition::Last => "after reviews",
+        }
+    }
+}
+
+fn default_leech_threshold() -> u32 {
+    8
+}
+
+fn default_relink_threshold() -> f64 {
+    0.9
 }
 
+// Cards in learning can be shown this much early if there is nothing else to review
+const LEARN_AHEAD_LIMIT: i64 = 20 * 60 * 1000;
+
 fn get_indices_to_review(cards: &[Card], date: Date) -> Vec<usize> {
     let mut items: Vec<usize> = Vec::new();
 
     for (index, card) in cards.iter().enumerate() {
-        if date.is_after(&card.fsrs_state.review_date) && !card.fsrs_state.buried {
+        if date.is_after(&card.fsrs_state.review_date) && !card.fsrs_state.is_hidden(&date) {
             items.push(index);
         }
     }
@@ -44,6 +188,86 @@ fn get_indices_to_review(cards: &[Card], date: Date) -> Vec<usize> {
     items
 }
 
+fn step_after_answer(index: usize, answer: ReviewAnswer) -> usize {
+    match answer {
+        ReviewAnswer::Again => 0,
+        ReviewAnswer::Good => index + 1,
+        _ => index,
+    }
+}
+
+// Key of the card if its file had the new name
+fn moved_key(card: &Card, to: &str) -> String {
+    let from = card.content.get_md_filename();
+    card_key(
+        &format!("{}{}", to, &card.content.prefix[from.len()..]),
+        &card.content.front,
+    )
+}
+
+// A file that disappeared is moved to a file that appeared when at least half of its cards are there with the same front and back
+fn detect_moved_files(
+    missing: &[Card],
+    new_cards: &HashMap<String, Card>,
+    old_files: &HashSet<String>,
+    new_files: &HashSet<String>,
+) -> HashMap<String, String> {
+    let mut moves: HashMap<String, String> = HashMap::new();
+    let disappeared = old_files.iter().filter(|x| !new_files.contains(*x));
+    let mut appeared: Vec<&String> = new_files
+        .iter()
+        .filter(|x| !old_files.contains(*x))
+        .collect();
+    appeared.sort();
+
+    for from in disappeared {
+        let cards: Vec<&Card> = missing
+            .iter()
+            .filter(|x| x.content.get_md_filename() == from)
+            .collect();
+        let best = appeared
+            .iter()
+            .map(|to| {
+                let count = cards
+                    .iter()
+                    .filter(|card| {
+                        new_cards
+                            .get(&moved_key(card, to))
+                            .is_some_and(|x| x.content.back == card.content.back)
+                    })
+                    .count();
+                (count, *to)
+            })
+            .max_by_key(|(count, _)| *count);
+
+        if let Some((count, to)) = best {
+            if count > 0 && count * 2 >= cards.len() {
+                moves.insert(from.clone(), to.clone());
+            }
+        }
+    }
+
+    moves
+}
+
+const ID_CHARS: &[u8] = b"0123456789abcdefghijklmnopqrstuvwxyz";
+
+// Derived from the key so that the ids are reproducible, another one is drawn when the id is taken
+fn new_card_id(key: &str, used: &mut HashSet<String>) -> String {
+    let mut generator = SplitMix64::from_seed(fnv1a(key));
+    loop {
+        let mut number = generator.next_rand();
+        let mut value = String::from("tm-");
+        for _ in 0..6 {
+            value.push(ID_CHARS[(number % 36) as usize] as char);
+            number /= 36;
+        }
+        if used.insert(value.clone()) {
+            return value;
+        }
+    }
+}
+
 fn fix_card_new_lines(mut card: Card) -> Card {
     let front_newlines = card.content.front.find('\n').is_some();
     let back_newlines = card.content.back.find('\n').is_some();
@@ -78,6 +302,25 @@ impl Deck {
             base_cards: vec![],
             track_review_history: false,
             params: FSRSParams::new(),
+            learning_steps: vec![],
+            relearning_steps: vec![],
+            presets: vec![],
+            leech_threshold: default_leech_threshold(),
+            leech_action: LeechAction::Flag,
+            bury_new_siblings: false,
+            bury_review_siblings: false,
+            new_cards_per_day: None,
+            reviews_per_day: None,
+            review_order: ReviewOrder::Random,
+            new_card_position: NewCardPosition::Mixed,
+            review_tag: None,
+            inline_schedule: false,
+            card_ids: CardIds::Off,
+            relink_threshold: default_relink_threshold(),
+            relink_proposals: vec![],
+            rejected_relinks: vec![],
+            moved_files: vec![],
+            scheduled_keys: vec![],
             parsing_version: crate::cardcache::PARSING_VERSION,
         }
     }
@@ -103,12 +346,21 @@ impl Deck {
     pub fn load_from_file() -> Result<Deck, Box<dyn std::error::Error>> {
         let file = fs::File::open("tmemodeck.json")?;
         let reader = BufReader::new(file);
-        let d = serde_json::from_reader(reader)?;
+        let d: Deck = serde_json::from_reader(reader)?;
+        if d.parsing_version < crate::cardcache::PARSING_VERSION {
+            return Err(
+                "The deck was saved by an older version of tmemo, run tmemo migrate".into(),
+            );
+        }
         Ok(d)
     }
 
     pub fn save_to_file(&mut self) -> Result<(), Box<dyn std::error::Error>> {
-        let edited: Vec<(Card, Card)> = self.edited_cards.drain(0..).collect();
+        let mut edited: Vec<(Card, Card)> = self.edited_cards.drain(0..).collect();
+        let keys = std::mem::take(&mut self.scheduled_keys);
+        if self.inline_schedule {
+            edited = self.with_inline_schedules(edited, keys);
+        }
         try_replacing_cards(edited);
 
         let file = fs::File::create("tmemodeck.json.temp")?;
@@ -118,6 +370,192 @@ impl Deck {
         Ok(())
     }
 
+    // Indices of the deck cards of each markdown card by its key, the clozes for base cards
+    fn markdown_card_indices(&self) -> HashMap<String, Vec<usize>> {
+        let base_keys: Vec<String> = self.base_cards.iter().map(|x| x.content.key()).collect();
+        let mut indices: HashMap<String, Vec<usize>> = HashMap::new();
+        for (index, card) in self.cards.iter().enumerate() {
+            let key = match card.content.base {
+                Some(base) => base_keys[base].clone(),
+                None => card.content.key(),
+            };
+            indices.entry(key).or_default().push(index);
+        }
+        indices
+    }
+
+    /// Schedules of the cards as written in the markdown, cloze cards have one for each cloze of the base card
+    fn inline_schedules_of(&self, indices: &[usize]) -> Vec<InlineSchedule> {
+        let mut cards: Vec<&Card> = indices.iter().map(|x| &self.cards[*x]).collect();
+        cards.sort_by_key(|x| x.content.cloze_index);
+        if cards.iter().all(|x| x.fsrs_state.is_new()) {
+            return vec![];
+        }
+        cards
+            .iter()
+            .map(|x| InlineSchedule::from_state(&x.fsrs_state))
+            .collect()
+    }
+
+    // The markdown card of the deck card, the base card for cloze cards
+    fn markdown_card(&self, card: &Card) -> Card {
+        match card.content.base {
+            Some(base) => self.base_cards[base].clone(),
+            None => card.clone(),
+        }
+    }
+
+    // Adds the schedules to the edited cards and rewrites the cards whose schedule changed
+    fn with_inline_schedules(
+        &self,
+        mut pairs: Vec<(Card, Card)>,
+        keys: Vec<String>,
+    ) -> Vec<(Card, Card)> {
+        let markdown_indices = self.markdown_card_indices();
+        let card_indices: HashMap<String, usize> = self
+            .cards
+            .iter()
+            .enumerate()
+            .map(|(index, card)| (card.content.key(), index))
+            .collect();
+        let schedules_of = |key: &String| match markdown_indices.get(key) {
+            Some(indices) => self.inline_schedules_of(indices),
+            None => vec![],
+        };
+
+        let mut written: HashSet<String> = HashSet::new();
+        for pair in pairs.iter_mut() {
+            let key = pair.1.content.key();
+            pair.1.content.inline_schedules = schedules_of(&key);
+            written.insert(key);
+        }
+
+        for key in keys {
+            let card = match card_indices.get(&key) {
+                Some(index) => self.markdown_card(&self.cards[*index]),
+                None => continue,
+            };
+            let key = card.content.key();
+            if !written.insert(key.clone()) {
+                continue;
+            }
+            let schedules = schedules_of(&key);
+            if schedules.is_empty() {
+                continue;
+            }
+            let mut new_card = card.clone();
+            new_card.content.inline_schedules = schedules;
+            pairs.push((card, new_card));
+        }
+        pairs
+    }
+
+    /// Writes the schedules of all the reviewed cards on the next save, or removes them when disabled
+    pub fn set_inline_schedule(&mut self, enabled: bool) {
+        self.inline_schedule = enabled;
+        if enabled {
+            self.scheduled_keys = self.cards.iter().map(|x| x.content.key()).collect();
+            return;
+        }
+
+        let mut written: HashSet<String> = HashSet::new();
+        for card in self.cards.iter().filter(|x| !x.fsrs_state.is_new()) {
+            let card = self.markdown_card(card);
+            if written.insert(card.content.key()) {
+                self.edited_cards.push((card.clone(), card));
+            }
+        }
+    }
+
+    /// Gives an id to the cards that don't have one yet, the ids are written on the next save
+    pub fn assign_card_ids(&mut self) {
+        let kind = match self.card_ids {
+            CardIds::Off => return,
+            CardIds::Block => CardId::Block,
+            CardIds::Comment => CardId::Comment,
+        };
+        let mut used: HashSet<String> = self
+            .cards
+            .iter()
+            .chain(self.base_cards.iter())
+            .filter_map(|x| x.content.id.as_ref().map(|id| id.value().to_string()))
+            .collect();
+
+        for index in 0..self.cards.len() {
+            let content = &self.cards[index].content;
+            if content.id.is_some() || content.base.is_some() || !content.editable {
+                continue;
+            }
+            let original = self.cards[index].clone();
+            let id = kind(new_card_id(&original.content.key(), &mut used));
+            self.cards[index].content.id = Some(id);
+            self.edited_cards
+                .push((original, self.cards[index].clone()));
+        }
+
+        for base in 0..self.base_cards.len() {
+            if self.base_cards[base].content.id.is_some() {
+                continue;
+            }
+            let original = self.base_cards[base].clone();
+            let id = kind(new_card_id(&original.content.key(), &mut used));
+            for card in self
+                .cards
+                .iter_mut()
+                .filter(|x| x.content.base == Some(base))
+            {
+                let value = format!("{}.{}", id.value(), card.content.cloze_index.unwrap_or(0));
+                card.content.id = Some(id.with_value(value));
+            }
+            self.base_cards[base].content.id = Some(id);
+            self.edited_cards
+                .push((original, self.base_cards[base].clone()));
+        }
+    }
+
+    /// Relinks the orphans that are similar enough to the new cards, the rest of the pairings are left to be confirmed
+    pub fn relink_orphans(&mut self, candidates: &[usize]) {
+        let rejected: HashSet<(String, String)> = self.rejected_relinks.iter().cloned().collect();
+        self.relink_proposals = propose_relinks(&self.cards, &self.orphans, candidates)
+            .into_iter()
+            .filter(|x| {
+                let pair = (
+                    self.orphans[x.orphan].content.key(),
+                    self.cards[x.card].content.key(),
+                );
+                !rejected.contains(&pair)
+            })
+            .collect();
+        let mut index = 0;
+        while index < self.relink_proposals.len() {
+            if self.relink_proposals[index].score >= self.relink_threshold {
+                self.accept_relink(index);
+            } else {
+                index += 1;
+            }
+        }
+    }
+
+    /// Gives the state of the orphan to the card of the proposal
+    pub fn accept_relink(&mut self, index: usize) {
+        let relink = self.relink_proposals.remove(index);
+        let orphan = self.orphans.remove(relink.orphan);
+        self.cards[relink.card].fsrs_state = orphan.fsrs_state;
+        for proposal in self.relink_proposals.iter_mut() {
+            if proposal.orphan > relink.orphan {
+                proposal.orphan -= 1;
+            }
+        }
+    }
+
+    pub fn reject_relink(&mut self, index: usize) {
+        let relink = self.relink_proposals.remove(index);
+        self.rejected_relinks.push((
+            self.orphans[relink.orphan].content.key(),
+            self.cards[relink.card].content.key(),
+        ));
+    }
+
     pub fn edit_card(&mut self, new_card: Card, card_index: usize) {
         if let Some(index) = self.cards[card_index].content.base {
             self.edit_base_card(index, new_card);
@@ -127,8 +565,11 @@ impl Deck {
     }
 
     fn edit_card_internal(&mut self, idx: usize, mut new_card: Card) {
-        let cards = vec![new_card.clone()];
         let original = self.cards[idx].clone();
+        if new_card.content.id.is_none() {
+            new_card.content.id = original.content.id.clone();
+        }
+        let cards = vec![new_card.clone()];
         let collection = CardCollection::from(cards).unwrap();
 
         if collection.base_cards.is_empty() {
@@ -149,6 +590,9 @@ impl Deck {
 
     pub fn edit_base_card(&mut self, base_card_index: usize, mut new_card: Card) {
         new_card = fix_card_new_lines(new_card);
+        if new_card.content.id.is_none() {
+            new_card.content.id = self.base_cards[base_card_index].content.id.clone();
+        }
         let cards = vec![new_card.clone()];
         let collection = CardCollection::from(cards).unwrap();
         self.edited_cards
@@ -181,49 +625,392 @@ impl Deck {
         }
     }
 
-    pub fn review_card(self: &mut Self, answer: ReviewAnswer, generator: &mut SplitMix64) {
-        let review_index = self.review_index.unwrap();
-        let card_index = self.review_indices[review_index];
+    pub fn review_card(&mut self, answer: ReviewAnswer, generator: &mut SplitMix64) {
+        self.review_card_with_timing(answer, None, generator);
+    }
+
+    // Current time when reviewing today, noon of the review date for reviews on other days such as in simulations
+    fn review_timestamp(&self) -> i64 {
+        match self.review_date {
+            Some(date) if date != Date::now() => date.noon_timestamp(),
+            _ => current_timestamp(),
+        }
+    }
+
+    pub fn review_card_with_timing(
+        &mut self,
+        answer: ReviewAnswer,
+        timing: Option<ReviewTiming>,
+        generator: &mut SplitMix64,
+    ) {
+        let card_index = self.review_indices[self.review_index.unwrap()];
+        // Siblings stay when the card is forgotten, since it is shown again anyway
+        if answer != ReviewAnswer::Bury && answer != ReviewAnswer::Again {
+            self.defer_siblings(card_index);
+        }
+        if self.inline_schedule {
+            self.scheduled_keys
+                .push(self.cards[card_index].content.key());
+        }
+        let review_index = self
+            .review_indices
+            .iter()
+            .position(|index| *index == card_index)
+            .unwrap();
+        let logged = self.track_review_history && answer != ReviewAnswer::Bury;
+        let now = match timing {
+            Some(timing) => timing.timestamp,
+            None => self.review_timestamp(),
+        };
+        let step = self.next_learning_step(&self.cards[card_index].fsrs_state, answer.clone());
+        let params = self.params_for(&self.cards[card_index]).clone();
         let result = self.cards[card_index].fsrs_state.review_with_rng(
-            answer,
+            answer.clone(),
             &self.review_date.unwrap(),
             self.track_review_history,
             generator,
-            &self.params,
+            &params,
         );
+        if let (true, Some(timing)) = (logged, timing) {
+            self.cards[card_index].fsrs_state.record_timing(&timing);
+        }
+
+        if answer == ReviewAnswer::Bury {
+            self.review_indices.remove(review_index);
+            self.gen_review_index(generator, now);
+            return;
+        }
+
+        if answer == ReviewAnswer::Again && self.check_leech(card_index) {
+            // Suspended leech is not shown again in this review
+            self.review_indices.remove(review_index);
+            self.gen_review_index(generator, now);
+            return;
+        }
+
+        if let Some((step, minutes)) = step {
+            let today = self.review_date.unwrap();
+            let due = now + minutes as i64 * 60 * 1000;
+            let due_date = Date::from_timestamp(due);
+            let state = &mut self.cards[card_index].fsrs_state;
+            state.learning_step = Some(step);
+            if due_date.day > today.day {
+                // Step carries over to another day, the card is shown normally on that day
+                state.review_date = due_date;
+                state.step_due = None;
+                self.review_indices.remove(review_index);
+            } else {
+                state.review_date = today;
+                state.step_due = Some(due);
+                self.requeue(review_index);
+            }
+            self.gen_review_index(generator, now);
+            return;
+        } else {
+            let state = &mut self.cards[card_index].fsrs_state;
+            state.learning_step = None;
+            state.step_due = None;
+        }
+
         match result {
             ReviewResult::Discard => {
-                let card : &Card = &self.cards[card_index];
+                let card: &Card = &self.cards[card_index];
                 if card.fsrs_state.stability > 2.0 {
                     let offset = self.card_review_offset(card.fsrs_state.review_date);
                     self.cards[card_index].fsrs_state.review_date.day += offset;
                 }
 
                 self.review_indices.remove(review_index)
-            },
-            _ => 0,
+            }
+            _ => {
+                self.requeue(review_index);
+                0
+            }
         };
-        self.gen_review_index(generator);
+        self.gen_review_index(generator, now);
     }
 
-    fn gen_review_index(self: &mut Self, generator: &mut SplitMix64) {
-        let count = self.review_indices.len();
-        if count == 0 {
-            self.review_index = None;
-        } else if count == 1 {
-            self.review_index = Some(0);
+    /// Takes the cloze siblings of the card out of the review, the nth sibling is deferred by n days
+    fn defer_siblings(&mut self, card_index: usize) {
+        let base = match self.cards[card_index].content.base {
+            Some(base) => base,
+            None => return,
+        };
+        if !self.bury_new_siblings && !self.bury_review_siblings {
+            return;
+        }
+
+        let today = self.review_date.unwrap();
+        let mut deferred = 0;
+        let mut review_indices = std::mem::take(&mut self.review_indices);
+        review_indices.retain(|index| {
+            let card = &mut self.cards[*index];
+            let sibling = *index != card_index && card.content.base == Some(base);
+            // Review siblings that aren't due yet keep their due date
+            let bury = if card.fsrs_state.is_new() {
+                self.bury_new_siblings
+            } else {
+                self.bury_review_siblings && card.fsrs_state.review_date.day <= today.day
+            };
+            if !sibling || !bury {
+                return true;
+            }
+
+            deferred += 1;
+            let day = today.checked_add_days(deferred).unwrap();
+            if card.fsrs_state.review_date.day < day.day {
+                card.fsrs_state.review_date = day;
+            }
+            false
+        });
+        self.review_indices = review_indices;
+    }
+
+    // Cards that stay in the review go after the rest when the review is ordered
+    fn requeue(&mut self, review_index: usize) {
+        if self.review_order != ReviewOrder::Random {
+            let card_index = self.review_indices.remove(review_index);
+            self.review_indices.push(card_index);
+        }
+    }
+
+    /// Marks the card as a leech when it crosses the lapse threshold, returns true if it was suspended
+    fn check_leech(&mut self, card_index: usize) -> bool {
+        let state = &mut self.cards[card_index].fsrs_state;
+        if state.leech || self.leech_threshold == 0 || state.lapses() < self.leech_threshold {
+            return false;
+        }
+
+        state.leech = true;
+        if self.leech_action == LeechAction::Suspend {
+            state.suspend(None);
+            state.learning_step = None;
+            state.step_due = None;
+            true
+        } else {
+            false
+        }
+    }
+
+    pub fn leech_indices(&self) -> Vec<usize> {
+        (0..self.cards.len())
+            .filter(|index| self.cards[*index].fsrs_state.leech)
+            .collect()
+    }
+
+    /// Clears the leech flag after the card has been rewritten, suspended leeches are brought back
+    pub fn clear_leech(&mut self, card_index: usize) {
+        let state = &mut self.cards[card_index].fsrs_state;
+        if state.leech && self.leech_action == LeechAction::Suspend {
+            state.unsuspend();
+        }
+        state.leech = false;
+    }
+
+    /// Index of the preset of the card, the preset named in the front matter of the file is used before the patterns
+    pub fn preset_index_for(&self, card: &Card) -> Option<usize> {
+        let named = card
+            .content
+            .preset
+            .as_ref()
+            .and_then(|name| self.presets.iter().position(|x| &x.name == name));
+        named.or_else(|| self.presets.iter().position(|x| x.matches(&card.content)))
+    }
+
+    pub fn params_for(&self, card: &Card) -> &FSRSParams {
+        match self.preset_index_for(card) {
+            Some(index) => &self.presets[index].params,
+            None => &self.params,
+        }
+    }
+
+    /// Returns the learning step the card moves to with the answer and its length in minutes, None if the card graduates
+    pub fn next_learning_step(
+        &self,
+        state: &FSRSState,
+        answer: ReviewAnswer,
+    ) -> Option<(LearningStep, u32)> {
+        let step = match (state.learning_step, answer.clone()) {
+            (_, ReviewAnswer::Easy) | (_, ReviewAnswer::Bury) => return None,
+            (Some(LearningStep::Learning(index)), _) => {
+                LearningStep::Learning(step_after_answer(index, answer))
+            }
+            (Some(LearningStep::Relearning(index)), _) => {
+                LearningStep::Relearning(step_after_answer(index, answer))
+            }
+            (None, ReviewAnswer::Good) if state.first_review() => LearningStep::Learning(1),
+            (None, _) if state.first_review() => LearningStep::Learning(0),
+            (None, ReviewAnswer::Again) => LearningStep::Relearning(0),
+            (None, _) => return None,
+        };
+        let (index, steps) = match step {
+            LearningStep::Learning(index) => (index, &self.learning_steps),
+            LearningStep::Relearning(index) => (index, &self.relearning_steps),
+        };
+        steps.get(index).map(|minutes| (step, *minutes))
+    }
+
+    /// Suspends the card under review and takes it out of the session
+    pub fn suspend_review_card(
+        &mut self,
+        until: Option<Date>,
+        generator: &mut SplitMix64,
+        now: i64,
+    ) {
+        let review_index = self.review_index.unwrap();
+        let card_index = self.review_indices.remove(review_index);
+        self.cards[card_index].fsrs_state.suspend(until);
+        self.gen_review_index(generator, now);
+    }
+
+    /// Suspended cards along with the ones buried until a later day
+    pub fn suspended_indices(&self, date: Date) -> Vec<usize> {
+        (0..self.cards.len())
+            .filter(|index| self.cards[*index].fsrs_state.is_hidden(&date))
+            .collect()
+    }
+
+    /// Timestamp of the earliest learning step that is still pending in the current review
+    pub fn next_step_due(&self) -> Option<i64> {
+        self.review_indices
+            .iter()
+            .filter_map(|index| self.cards[*index].fsrs_state.step_due)
+            .min()
+    }
+
+    /// Picks a card again after waiting for a learning step to become due
+    pub fn refresh_review_index(&mut self, now: i64, generator: &mut SplitMix64) {
+        self.gen_review_index(generator, now);
+    }
+
+    fn gen_review_index(self: &mut Self, generator: &mut SplitMix64, now: i64) {
+        if self.review_order != ReviewOrder::Random
+            || self.new_card_position != NewCardPosition::Mixed
+        {
+            self.gen_ordered_review_index(generator, now);
+            return;
+        }
+
+        let available = self.available_review_indices(now);
+        if available.len() <= 1 {
+            self.review_index = available.first().copied();
         } else {
-            let mut new_index = generator.next_rand() as usize % self.review_indices.len();
+            let mut new_index = generator.next_rand() as usize % available.len();
 
-            if self.review_index.is_some() {
-                while new_index == self.review_index.unwrap() {
-                    new_index += 1;
-                    new_index %= self.review_indices.len();
+            while Some(available[new_index]) == self.review_index {
+                new_index += 1;
+                new_index %= available.len();
+            }
+
+            self.review_index = Some(available[new_index]);
+        }
+    }
+
+    fn gen_ordered_review_index(&mut self, generator: &mut SplitMix64, now: i64) {
+        let mut available = self.available_review_indices(now);
+        let is_new = |index: &usize| self.cards[self.review_indices[*index]].fsrs_state.is_new();
+
+        let prefer_new = match self.new_card_position {
+            NewCardPosition::Mixed => None,
+            NewCardPosition::First => Some(true),
+            NewCardPosition::Last => Some(false),
+        };
+        if let Some(prefer_new) = prefer_new {
+            if available.iter().any(|x| is_new(x) == prefer_new) {
+                available.retain(|x| is_new(x) == prefer_new);
+            }
+        }
+
+        self.review_index = match (available.len(), &self.review_order) {
+            (0, _) => None,
+            (len, ReviewOrder::Random) => {
+                if len > 1 {
+                    available.retain(|x| Some(*x) != self.review_index);
                 }
+                Some(available[generator.next_rand() as usize % available.len()])
             }
+            // The indices are kept in order, cards answered again are moved to the end
+            _ => Some(available[0]),
+        };
+    }
 
-            self.review_index = Some(new_index);
+    fn step_due_at(&self, index: usize) -> i64 {
+        self.cards[self.review_indices[index]]
+            .fsrs_state
+            .step_due
+            .unwrap_or(i64::MIN)
+    }
+
+    // Positions of the cards in review that can be shown now
+    fn available_review_indices(&self, now: i64) -> Vec<usize> {
+        let count = self.review_indices.len();
+        let mut available: Vec<usize> =
+            (0..count).filter(|x| self.step_due_at(*x) <= now).collect();
+        if available.is_empty() {
+            // Learn ahead if the next step is close enough
+            if let Some(index) = (0..count).min_by_key(|x| self.step_due_at(*x)) {
+                if self.step_due_at(index) - now <= LEARN_AHEAD_LIMIT {
+                    available.push(index);
+                }
+            }
         }
+        available
+    }
+
+    /// Sorts the cards of the review according to the review order
+    fn order_review_indices(&mut self, date: Date) {
+        let mut indices = std::mem::take(&mut self.review_indices);
+        let retention = |index: &usize| {
+            let card = &self.cards[*index];
+            if card.fsrs_state.is_new() {
+                0.0
+            } else {
+                card.fsrs_state.retention(&date, self.params_for(card))
+            }
+        };
+
+        match self.review_order {
+            ReviewOrder::Random => (),
+            ReviewOrder::LowestRetrievability => {
+                indices.sort_by(|a, b| retention(a).total_cmp(&retention(b)))
+            }
+            ReviewOrder::MostOverdue => {
+                // Overdue days relative to the interval, largest first
+                let overdue = |index: &usize| {
+                    let state = &self.cards[*index].fsrs_state;
+                    let interval = (state.review_date.day - state.last_review.day).max(1);
+                    (date.day - state.review_date.day) as f64 / interval as f64
+                };
+                indices.sort_by(|a, b| overdue(b).total_cmp(&overdue(a)))
+            }
+            ReviewOrder::DueDate => {
+                indices.sort_by_key(|index| self.cards[*index].fsrs_state.review_date.day)
+            }
+            ReviewOrder::GroupedByFile => indices.sort_by(|a, b| {
+                self.cards[*a]
+                    .content
+                    .prefix
+                    .cmp(&self.cards[*b].content.prefix)
+            }),
+            ReviewOrder::InterleavedByPrefix => {
+                // Take one card from each prefix in turn
+                let mut groups: BTreeMap<&str, Vec<usize>> = BTreeMap::new();
+                for index in &indices {
+                    let prefix = self.cards[*index].content.prefix.as_str();
+                    groups.entry(prefix).or_default().push(*index);
+                }
+                let longest = groups.values().map(|x| x.len()).max().unwrap_or(0);
+                indices = (0..longest)
+                    .flat_map(|i| {
+                        groups
+                            .values()
+                            .filter_map(move |group| group.get(i).copied())
+                    })
+                    .collect();
+            }
+        }
+
+        self.review_indices = indices;
     }
 
     pub fn start_random_review(
@@ -234,12 +1021,17 @@ impl Deck {
     ) {
         self.review_indices.clear();
         self.review_indices.reserve(review_count);
-        review_count = review_count.min(self.cards.len());
+        let visible_count = self
+            .cards
+            .iter()
+            .filter(|card| !card.fsrs_state.is_hidden(&date))
+            .count();
+        review_count = review_count.min(visible_count);
         for _i in 0..review_count {
             let mut new_index = generator.next_rand() as usize % self.cards.len();
 
             while self.review_indices.contains(&new_index)
-                || self.cards[new_index].fsrs_state.buried
+                || self.cards[new_index].fsrs_state.is_hidden(&date)
             {
                 new_index += 1;
                 new_index %= self.cards.len();
@@ -247,29 +1039,138 @@ impl Deck {
             self.review_indices.push(new_index);
         }
         self.review_date = Some(date);
-        self.gen_review_index(generator);
+        self.order_review_indices(date);
+        let now = self.review_timestamp();
+        self.gen_review_index(generator, now);
+    }
+
+    /// Tags of the cards sorted without case, the same tag written differently is listed once
+    pub fn all_tags(&self) -> Vec<String> {
+        let mut tags: Vec<String> = vec![];
+        for card in &self.cards {
+            merge_tags(&mut tags, &card.content.tags);
+        }
+        tags.sort_by_key(|x| x.to_lowercase());
+        tags
+    }
+
+    /// The tag after the review tag in the list of tags, no tag after the last one
+    pub fn next_review_tag(&self) -> Option<String> {
+        let tags = self.all_tags();
+        let position = match &self.review_tag {
+            Some(tag) => tags
+                .iter()
+                .position(|x| x.to_lowercase() == tag.to_lowercase()),
+            None => return tags.first().cloned(),
+        };
+        match position {
+            Some(index) => tags.get(index + 1).cloned(),
+            None => tags.first().cloned(),
+        }
+    }
+
+    fn has_review_tag(&self, card: &Card) -> bool {
+        match &self.review_tag {
+            Some(filter) => card.content.tags.iter().any(|tag| tag_matches(tag, filter)),
+            None => true,
+        }
     }
 
     pub fn start_all_review(&mut self, date: Date, generator: &mut SplitMix64) {
         self.review_indices.clear();
         self.review_indices.reserve(self.cards.len());
         for i in 0..self.cards.len() {
-            if !self.cards[i].fsrs_state.buried {
+            if !self.cards[i].fsrs_state.is_hidden(&date) && self.has_review_tag(&self.cards[i]) {
                 self.review_indices.push(i);
             }
         }
         self.review_date = Some(date);
-        self.gen_review_index(generator);
+        self.order_review_indices(date);
+        let now = self.review_timestamp();
+        self.gen_review_index(generator, now);
     }
 
     pub fn start_review(self: &mut Self, date: Date, generator: &mut SplitMix64) {
-        self.review_indices = get_indices_to_review(&self.cards, date);
+        self.review_indices = self.limited_indices_to_review(date);
         self.review_date = Some(date);
-        self.gen_review_index(generator);
+        self.order_review_indices(date);
+        let now = self.review_timestamp();
+        self.gen_review_index(generator, now);
     }
 
     pub fn cards_to_review_count(&self, date: Date) -> usize {
-        get_indices_to_review(&self.cards, date).len()
+        self.limited_indices_to_review(date).len()
+    }
+
+    /// Number of new cards and reviews left for the day after the daily limits
+    pub fn due_counts(&self, date: Date) -> (usize, usize) {
+        let indices = self.limited_indices_to_review(date);
+        let new = indices
+            .iter()
+            .filter(|index| self.cards[**index].fsrs_state.is_new())
+            .count();
+        (new, indices.len() - new)
+    }
+
+    // New cards introduced and review cards studied on the date
+    fn studied_counts(&self, date: Date) -> (usize, usize) {
+        let mut new = 0;
+        let mut reviews = 0;
+        for card in &self.cards {
+            let state = &card.fsrs_state;
+            let log = &state.review_log;
+            // Without the review history only the last review and the first one are known
+            let reviewed = log.iter().any(|item| item.day == date)
+                || (state.last_review == date && !state.is_new());
+            if !reviewed {
+                continue;
+            }
+            if state.introduced == Some(date)
+                || (state.complete_history && log.first().is_some_and(|x| x.day == date))
+            {
+                new += 1;
+            } else {
+                reviews += 1;
+            }
+        }
+        (new, reviews)
+    }
+
+    fn limited_indices_to_review(&self, date: Date) -> Vec<usize> {
+        let mut indices = get_indices_to_review(&self.cards, date);
+        indices.retain(|index| self.has_review_tag(&self.cards[*index]));
+        if self.new_cards_per_day.is_none() && self.reviews_per_day.is_none() {
+            return indices;
+        }
+
+        let (studied_new, studied_reviews) = self.studied_counts(date);
+        let remaining = |limit: Option<u32>, studied: usize| match limit {
+            Some(limit) => (limit as usize).saturating_sub(studied),
+            None => usize::MAX,
+        };
+        let (mut new, mut reviews): (Vec<usize>, Vec<usize>) = indices
+            .into_iter()
+            .partition(|index| self.cards[*index].fsrs_state.is_new());
+        // Cards in learning steps and cards already answered today, such as forgotten ones, are always finished
+        let (learning, mut reviews): (Vec<usize>, Vec<usize>) =
+            reviews.drain(0..).partition(|index| {
+                let state = &self.cards[*index].fsrs_state;
+                state.learning_step.is_some()
+                    || state.last_review == date
+                    || state.review_log.iter().any(|x| x.day == date)
+            });
+
+        // Oldest new cards and the most overdue reviews are taken first
+        new.sort_by_key(|index| self.cards[*index].fsrs_state.date_added.day);
+        new.truncate(remaining(self.new_cards_per_day, studied_new));
+        reviews.sort_by_key(|index| self.cards[*index].fsrs_state.review_date.day);
+        reviews.truncate(remaining(self.reviews_per_day, studied_reviews));
+
+        let mut output = learning;
+        output.extend(new);
+        output.extend(reviews);
+        output.sort();
+        output
     }
 
     pub fn active_review_count(self: &Self) -> usize {
@@ -302,7 +1203,7 @@ impl Deck {
 
     pub fn random_reschedule_fractional(&mut self, frac_diff: f64, generator: &mut SplitMix64) {
         for card in self.cards.iter_mut() {
-            if card.fsrs_state.buried {
+            if card.fsrs_state.suspended {
                 continue;
             }
 
@@ -322,7 +1223,9 @@ impl Deck {
         let mut total_cards_for_days = 0.0;
         let mut indices: Vec<usize> = Vec::new();
         for (index, card) in self.cards.iter().enumerate() {
-            if !card.fsrs_state.buried && card.fsrs_state.review_date.day - first_day.day < days {
+            if !card.fsrs_state.is_suspended(&first_day)
+                && card.fsrs_state.review_date.day - first_day.day < days
+            {
                 total_cards_for_days += 1.0;
                 indices.push(index);
             }
@@ -367,10 +1270,102 @@ impl Deck {
         }
     }
 
+    // Cards pasted with the id of another card get a new id, the card matching the deck card of the id keeps it
+    fn renew_duplicate_ids(&mut self, collection: &mut CardCollection) {
+        let deck_keys: HashMap<String, String> = self
+            .cards
+            .iter()
+            .chain(self.base_cards.iter())
+            .filter_map(|x| {
+                let id = x.content.id.as_ref()?;
+                Some((id.value().to_string(), x.content.key()))
+            })
+            .collect();
+        let mut used: HashSet<String> = collection
+            .cards
+            .iter()
+            .chain(collection.base_cards.iter())
+            .filter_map(|x| x.content.id.as_ref().map(|id| id.value().to_string()))
+            .collect();
+
+        // Base cards and the cards without one as written in the markdown
+        let mut order: Vec<String> = vec![];
+        let mut holders: HashMap<String, Vec<(bool, usize)>> = HashMap::new();
+        let markdown_cards = collection
+            .base_cards
+            .iter()
+            .enumerate()
+            .map(|(index, card)| (true, index, card))
+            .chain(
+                collection
+                    .cards
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, card)| card.content.base.is_none())
+                    .map(|(index, card)| (false, index, card)),
+            );
+        for (is_base, index, card) in markdown_cards {
+            if let Some(id) = &card.content.id {
+                let entry = holders.entry(id.value().to_string()).or_default();
+                if entry.is_empty() {
+                    order.push(id.value().to_string());
+                }
+                entry.push((is_base, index));
+            }
+        }
+
+        for value in order {
+            let holders = &holders[&value];
+            if holders.len() < 2 {
+                continue;
+            }
+            let key_of = |(is_base, index): (bool, usize)| match is_base {
+                true => collection.base_cards[index].content.key(),
+                false => collection.cards[index].content.key(),
+            };
+            let keeper = holders
+                .iter()
+                .position(|x| deck_keys.get(&value) == Some(&key_of(*x)))
+                .unwrap_or(0);
+
+            for (position, (is_base, index)) in holders.iter().copied().enumerate() {
+                if position == keeper {
+                    continue;
+                }
+                let card = match is_base {
+                    true => &mut collection.base_cards[index],
+                    false => &mut collection.cards[index],
+                };
+                let original = card.clone();
+                let id = original
+                    .content
+                    .id
+                    .as_ref()
+                    .unwrap()
+                    .with_value(new_card_id(&original.content.key(), &mut used));
+                card.content.id = Some(id.clone());
+                let updated = card.clone();
+                if is_base {
+                    for child in collection
+                        .cards
+                        .iter_mut()
+                        .filter(|x| x.content.base == Some(index))
+                    {
+                        let value =
+                            format!("{}.{}", id.value(), child.content.cloze_index.unwrap_or(0));
+                        child.content.id = Some(id.with_value(value));
+                    }
+                }
+                self.edited_cards.push((original, updated));
+            }
+        }
+    }
+
     pub fn replace_cards(
         &mut self,
-        collection: CardCollection,
+        mut collection: CardCollection,
     ) -> Result<(), Box<dyn std::error::Error>> {
+        self.renew_duplicate_ids(&mut collection);
         let mut map: HashMap<String, Card> = HashMap::new();
         let new_cards = collection.cards;
         self.base_cards = collection.base_cards;
@@ -387,27 +1382,85 @@ impl Deck {
             }
         }
 
+        let mut ids: HashMap<String, String> = HashMap::new();
+        for (key, card) in &map {
+            if let Some(id) = &card.content.id {
+                ids.insert(id.value().to_string(), key.clone());
+            }
+        }
+
+        let old_files: HashSet<String> = self
+            .cards
+            .iter()
+            .map(|x| x.content.get_md_filename().to_string())
+            .collect();
+        let new_files: HashSet<String> = map
+            .values()
+            .map(|x| x.content.get_md_filename().to_string())
+            .collect();
+
         let mut updated_cards: Vec<Card> = Vec::new();
+        let mut unmatched: Vec<Card> = Vec::new();
         let old_cards = self.cards.drain(0..);
 
+        // Cards are matched by id first, so that edited and moved cards keep their state
         for mut card in old_cards {
+            let key = card.content.id.as_ref().and_then(|id| ids.get(id.value()));
+            match key.and_then(|key| map.remove(key)) {
+                Some(new_entry) => {
+                    card.content = new_entry.content;
+                    updated_cards.push(card);
+                }
+                None => unmatched.push(card),
+            }
+        }
+
+        let mut missing: Vec<Card> = Vec::new();
+        for mut card in unmatched {
             let key = card.content.key();
             if map.contains_key(&key) {
                 let new_entry = map.remove(&key).unwrap();
                 card.content = new_entry.content;
                 updated_cards.push(card);
             } else {
-                self.orphans.push(card);
+                missing.push(card);
             }
         }
 
-        for (_, mut card) in map {
-            for i in 0..self.orphans.len() {
-                if &self.orphans[i].content.front == &card.content.front {
-                    let orphan = self.orphans.remove(i);
-                    card.fsrs_state = orphan.fsrs_state;
-                    break;
+        // Cards of a renamed or moved file are relabeled instead of becoming orphans
+        let moves = detect_moved_files(&missing, &map, &old_files, &new_files);
+        for mut card in missing {
+            let key = moves
+                .get(card.content.get_md_filename())
+                .map(|to| moved_key(&card, to));
+            match key.and_then(|key| map.remove(&key)) {
+                Some(new_entry) => {
+                    card.content = new_entry.content;
+                    updated_cards.push(card);
                 }
+                None => self.orphans.push(card),
+            }
+        }
+        self.moved_files = moves.into_iter().collect();
+        self.moved_files.sort();
+
+        // Cards that didn't match an orphan can still be similar enough to one
+        for (_, mut card) in map {
+            let same_id = |orphan: &Card| {
+                card.content.id.is_some()
+                    && orphan.content.id.as_ref().map(|x| x.value())
+                        == card.content.id.as_ref().map(|x| x.value())
+            };
+            let position = match self.orphans.iter().position(same_id) {
+                Some(i) => Some(i),
+                None => self
+                    .orphans
+                    .iter()
+                    .position(|orphan| orphan.content.front == card.content.front),
+            };
+            if let Some(i) = position {
+                let orphan = self.orphans.remove(i);
+                card.fsrs_state = orphan.fsrs_state;
             }
 
             updated_cards.push(card);
@@ -415,13 +1468,22 @@ impl Deck {
 
         self.cards = updated_cards;
         self.cards.sort();
+        // Proposals aren't saved, so they are made again on every update for the cards that are still new
+        let candidates: Vec<usize> = (0..self.cards.len())
+            .filter(|i| self.cards[*i].fsrs_state.is_new())
+            .collect();
+        let orphan_keys: HashSet<String> = self.orphans.iter().map(|x| x.content.key()).collect();
+        self.rejected_relinks
+            .retain(|(orphan, _)| orphan_keys.contains(orphan));
+        self.relink_orphans(&candidates);
+        self.assign_card_ids();
         Ok(())
     }
 
     pub fn print_card_data(&self) {
         let current_date = Date::now();
         for card in &self.cards {
-            if card.fsrs_state.buried {
+            if card.fsrs_state.is_suspended(&current_date) {
                 continue;
             }
             println!("{}", card.format_to_tsv(current_date));
@@ -472,6 +1534,7 @@ impl Deck {
         map
     }
 
+    /// Cards containing all the words, words starting with # match the tags of the cards
     pub fn find_cards(&self, search_input: String) -> Vec<usize> {
         let mut card_indices: Vec<usize> = (0..self.cards.len()).collect();
         let words = search_input.split_whitespace();
@@ -479,7 +1542,13 @@ impl Deck {
         for word in words {
             card_indices = card_indices
                 .into_iter()
-                .filter(|index| self.cards[*index].contains(word))
+                .filter(|index| {
+                    let card = &self.cards[*index];
+                    match word.len() > 1 && word.starts_with('#') {
+                        true => card.content.tags.iter().any(|tag| tag_matches(tag, word)),
+                        false => card.contains(word),
+                    }
+                })
                 .collect();
         }
 
@@ -512,6 +1581,11 @@ mod tests {
                 editable: true,
                 base: None,
                 cloze_index: None,
+                inline_schedules: vec![],
+                id: None,
+                tags: vec![],
+                preset: None,
+                reverse: false,
             },
             fsrs_state: FSRSState::new(default_date()),
         }
@@ -526,6 +1600,11 @@ mod tests {
                 editable: true,
                 base: None,
                 cloze_index: None,
+                inline_schedules: vec![],
+                id: None,
+                tags: vec![],
+                preset: None,
+                reverse: false,
             },
             fsrs_state: FSRSState::new(date),
         }
@@ -540,6 +1619,11 @@ mod tests {
                 editable: true,
                 base: None,
                 cloze_index: None,
+                inline_schedules: vec![],
+                id: None,
+                tags: vec![],
+                preset: None,
+                reverse: false,
             },
             fsrs_state: FSRSState::new(default_date()),
         }
@@ -590,6 +1674,35 @@ mod tests {
         assert_eq!(deck.cards[2].content.front, "front1\n\nback3 back4 {...}");
     }
 
+    #[test]
+    fn inline_schedules_are_written_once_per_note() {
+        let cards = vec![
+            new_card_with_back("front1", "{{{back1}}} {{{back2}}}"),
+            new_card("other"),
+        ];
+        let mut deck = Deck::new();
+        let _ = deck.replace_cards(CardCollection::from(cards).unwrap());
+        let params = deck.params.clone();
+        for card in deck
+            .cards
+            .iter_mut()
+            .filter(|x| x.content.cloze_index.is_some())
+        {
+            card.fsrs_state
+                .review(ReviewAnswer::Good, &default_date(), true, 1.0, &params);
+        }
+
+        let keys: Vec<String> = deck.cards.iter().map(|x| x.content.key()).collect();
+        let pairs = deck.with_inline_schedules(vec![], keys);
+        assert_eq!(pairs.len(), 1);
+        assert_eq!(pairs[0].1.content.front, "front1");
+        assert_eq!(pairs[0].1.content.inline_schedules.len(), 2);
+        assert_eq!(
+            pairs[0].1.content.inline_schedules[0].last_review,
+            Some(default_date())
+        );
+    }
+
     #[test]
     fn reschedule_works() {
         let mut deck = Deck::new();
@@ -646,6 +1759,438 @@ mod tests {
         assert_eq!(deck.cards[2].fsrs_state.review_date, date(2024, 1, 16));
     }
 
+    #[test]
+    fn review_timing_is_logged() {
+        let mut deck = Deck::new();
+        deck.track_review_history = true;
+        let collection = CardCollection::from(vec![new_card("card1")]).unwrap();
+        let _ = deck.replace_cards(collection);
+        let mut generator = SplitMix64::from_seed(42);
+        deck.start_review(default_date(), &mut generator);
+        let timing = ReviewTiming {
+            timestamp: 1704110400000,
+            duration: 3500,
+        };
+        deck.review_card_with_timing(ReviewAnswer::Good, Some(timing), &mut generator);
+        let log = &deck.cards[0].fsrs_state.review_log;
+        assert_eq!(log.len(), 1);
+        assert_eq!(log[0].timestamp, Some(1704110400000));
+        assert_eq!(log[0].duration, Some(3500));
+    }
+
+    fn timing(timestamp: i64) -> Option<ReviewTiming> {
+        Some(ReviewTiming {
+            timestamp,
+            duration: 1000,
+        })
+    }
+
+    #[test]
+    fn learning_steps_work() {
+        let mut deck = Deck::new();
+        deck.learning_steps = vec![1, 30];
+        let collection =
+            CardCollection::from(vec![new_card_with_date("card1", date(2023, 12, 1))]).unwrap();
+        let _ = deck.replace_cards(collection);
+        let mut generator = SplitMix64::from_seed(42);
+        let now = 1704110400000;
+        deck.start_review(Date::from_timestamp(now), &mut generator);
+
+        // Again goes to the first step, which is close enough to learn ahead
+        deck.review_card_with_timing(ReviewAnswer::Again, timing(now), &mut generator);
+        assert_eq!(
+            deck.cards[0].fsrs_state.learning_step,
+            Some(LearningStep::Learning(0))
+        );
+        assert_eq!(deck.review_index, Some(0));
+
+        // Second step has to be waited for
+        deck.review_card_with_timing(ReviewAnswer::Good, timing(now), &mut generator);
+        assert_eq!(
+            deck.cards[0].fsrs_state.learning_step,
+            Some(LearningStep::Learning(1))
+        );
+        assert_eq!(deck.review_index, None);
+        assert_eq!(deck.active_review_count(), 1);
+        assert_eq!(deck.next_step_due(), Some(now + 30 * 60 * 1000));
+
+        let later = now + 30 * 60 * 1000;
+        deck.refresh_review_index(later, &mut generator);
+        assert_eq!(deck.review_index, Some(0));
+        deck.review_card_with_timing(ReviewAnswer::Good, timing(later), &mut generator);
+        assert_eq!(deck.cards[0].fsrs_state.learning_step, None);
+        assert_eq!(deck.active_review_count(), 0);
+    }
+
+    #[test]
+    fn untimed_reviews_use_the_review_date() {
+        let mut deck = Deck::new();
+        deck.learning_steps = vec![60];
+        let collection =
+            CardCollection::from(vec![new_card_with_date("card1", date(2023, 12, 1))]).unwrap();
+        let _ = deck.replace_cards(collection);
+        let mut generator = SplitMix64::from_seed(42);
+        let day = date(2024, 1, 1);
+        deck.start_review(day, &mut generator);
+
+        // The step is due later on the simulated day instead of on the real day
+        deck.review_card(ReviewAnswer::Again, &mut generator);
+        assert_eq!(deck.active_review_count(), 1);
+        assert_eq!(
+            deck.next_step_due(),
+            Some(day.noon_timestamp() + 60 * 60 * 1000)
+        );
+        assert_eq!(deck.cards[0].fsrs_state.review_date, day);
+    }
+
+    #[test]
+    fn learning_step_carries_over_to_next_day() {
+        let mut deck = Deck::new();
+        deck.relearning_steps = vec![24 * 60];
+        let mut card = new_card_with_date("card1", date(2023, 12, 1));
+        card.fsrs_state.review(
+            ReviewAnswer::Good,
+            &date(2023, 12, 1),
+            true,
+            1.0,
+            &deck.params,
+        );
+        let _ = deck.replace_cards(CardCollection::from(vec![card]).unwrap());
+        let mut generator = SplitMix64::from_seed(42);
+        let now = 1704110400000;
+        deck.start_review(Date::from_timestamp(now), &mut generator);
+        deck.review_card_with_timing(ReviewAnswer::Again, timing(now), &mut generator);
+
+        let state = &deck.cards[0].fsrs_state;
+        assert_eq!(state.learning_step, Some(LearningStep::Relearning(0)));
+        assert_eq!(state.step_due, None);
+        assert_eq!(
+            state.review_date,
+            Date::from_timestamp(now + 24 * 60 * 60 * 1000)
+        );
+        assert_eq!(deck.active_review_count(), 0);
+    }
+
+    #[test]
+    fn presets_are_used_for_matching_cards() {
+        let mut deck = Deck::new();
+        let mut params = FSRSParams::new();
+        params.target_retention = 0.95;
+        deck.presets.push(Preset {
+            name: "languages".to_string(),
+            patterns: vec!["languages/**".to_string()],
+            params,
+        });
+        deck.presets.push(Preset {
+            name: "trivia".to_string(),
+            patterns: vec!["trivia.md > Capitals".to_string()],
+            params: FSRSParams::new(),
+        });
+        deck.presets[1].params.target_retention = 0.8;
+
+        // Prefixes come from the files as in a vault
+        let root = std::env::temp_dir().join(format!("tmemo-presets-{}", std::process::id()));
+        fs::create_dir_all(root.join("languages")).unwrap();
+        fs::write(
+            root.join("languages/spanish.md"),
+            "# Verbs\ncomer:: to eat\n",
+        )
+        .unwrap();
+        fs::write(
+            root.join("trivia.md"),
+            "x:: y\n# Capitals\nSpain:: Madrid\n",
+        )
+        .unwrap();
+        let mut cards = vec![];
+        for file in crate::cardcache::get_md_files_in_path(root.as_os_str()) {
+            let contents = crate::parsing::read_to_string(&file.path);
+            cards.extend(crate::parsing::parse_cards(
+                &contents,
+                default_date(),
+                Some(file.relative_path),
+            ));
+        }
+        fs::remove_dir_all(&root).unwrap();
+        cards.sort_by_key(|x| x.content.front.clone());
+        let _ = deck.replace_cards(CardCollection::from(cards).unwrap());
+        deck.cards.sort_by_key(|x| x.content.front.clone());
+        let fronts: Vec<&str> = deck
+            .cards
+            .iter()
+            .map(|x| x.content.front.as_str())
+            .collect();
+        assert_eq!(fronts, vec!["Spain", "comer", "x"]);
+        assert_eq!(deck.cards[1].content.prefix, "languages/spanish.md > Verbs");
+
+        assert_eq!(deck.params_for(&deck.cards[0]).target_retention, 0.8);
+        assert_eq!(deck.params_for(&deck.cards[1]).target_retention, 0.95);
+        assert_eq!(deck.params_for(&deck.cards[2]).target_retention, 0.9);
+        let mut named = deck.cards[2].clone();
+        named.content.preset = Some("languages".to_string());
+        assert_eq!(deck.params_for(&named).target_retention, 0.95);
+
+        let mut generator = SplitMix64::from_seed(42);
+        deck.start_all_review(default_date(), &mut generator);
+        while deck.get_review_card().is_some() {
+            deck.review_card(ReviewAnswer::Easy, &mut generator);
+        }
+        // Higher retention means a shorter interval
+        let day = |index: usize| deck.cards[index].fsrs_state.review_date.day;
+        assert!(day(1) < day(2) && day(2) < day(0));
+    }
+
+    #[test]
+    fn leeches_are_suspended() {
+        let mut deck = Deck::new();
+        deck.track_review_history = true;
+        deck.leech_threshold = 2;
+        deck.leech_action = LeechAction::Suspend;
+        let _ = deck.replace_cards(CardCollection::from(vec![new_card("card1")]).unwrap());
+        let mut generator = SplitMix64::from_seed(42);
+        let mut day = default_date();
+
+        deck.start_review(day, &mut generator);
+        deck.review_card(ReviewAnswer::Good, &mut generator);
+        for lapse in 0..2 {
+            day = deck.cards[0].fsrs_state.review_date;
+            deck.start_review(day, &mut generator);
+            deck.review_card(ReviewAnswer::Again, &mut generator);
+            if lapse == 0 {
+                assert!(!deck.cards[0].fsrs_state.leech);
+                deck.review_card(ReviewAnswer::Good, &mut generator);
+            }
+        }
+
+        assert!(deck.cards[0].fsrs_state.leech);
+        assert!(deck.cards[0].fsrs_state.suspended);
+        assert_eq!(deck.active_review_count(), 0);
+        assert_eq!(deck.leech_indices(), vec![0]);
+        deck.clear_leech(0);
+        assert!(!deck.cards[0].fsrs_state.suspended);
+        assert!(deck.leech_indices().is_empty());
+    }
+
+    #[test]
+    fn cards_are_filtered_by_tag() {
+        let input = "---\ntags: languages\n---\n\
+                     # Verbs #languages/spanish\n\
+                     comer:: to eat\n\
+                     # Trivia\n\
+                     capital of #geography France:: Paris\n";
+        let cards = crate::parsing::parse_cards(input, default_date(), None);
+        let mut deck = Deck::new();
+        let _ = deck.replace_cards(CardCollection::from(cards).unwrap());
+        let find = |search: &str| -> Vec<String> {
+            let mut fronts: Vec<String> = deck
+                .find_cards(search.to_string())
+                .iter()
+                .map(|x| deck.cards[*x].content.front.clone())
+                .collect();
+            fronts.sort();
+            fronts
+        };
+        assert_eq!(find("#spanish"), Vec::<String>::new());
+        assert_eq!(find("#languages/Spanish"), vec!["comer"]);
+        assert_eq!(
+            find("#languages"),
+            vec!["capital of #geography France", "comer"]
+        );
+        assert_eq!(
+            find("#geography capital"),
+            vec!["capital of #geography France"]
+        );
+
+        assert_eq!(
+            deck.all_tags(),
+            vec!["geography", "languages", "languages/spanish"]
+        );
+        assert_eq!(deck.next_review_tag().as_deref(), Some("geography"));
+        deck.review_tag = Some("languages/spanish".to_string());
+        assert_eq!(deck.next_review_tag(), None);
+        assert_eq!(deck.cards_to_review_count(default_date()), 1);
+
+        let mut generator = SplitMix64::from_seed(42);
+        deck.start_all_review(default_date(), &mut generator);
+        assert_eq!(deck.active_review_count(), 1);
+        assert_eq!(deck.get_review_card().unwrap().content.front, "comer");
+    }
+
+    #[test]
+    fn tagging_keeps_the_state() {
+        let cards = crate::parsing::parse_cards("# Verbs\ncomer:: to eat\n", default_date(), None);
+        let mut deck = Deck::new();
+        let _ = deck.replace_cards(CardCollection::from(cards).unwrap());
+        deck.cards[0].fsrs_state.stability = 5.0;
+
+        let cards = crate::parsing::parse_cards(
+            "# Verbs #spanish\ncomer #verb:: to eat\n",
+            default_date(),
+            None,
+        );
+        let _ = deck.replace_cards(CardCollection::from(cards).unwrap());
+        assert_eq!(deck.cards.len(), 1);
+        assert!(deck.orphans.is_empty());
+        assert_eq!(deck.cards[0].fsrs_state.stability, 5.0);
+        assert_eq!(deck.cards[0].content.tags, vec!["spanish", "verb"]);
+        assert_eq!(deck.cards[0].content.front, "comer #verb");
+    }
+
+    #[test]
+    fn review_orders_work() {
+        let mut deck = Deck::new();
+        let mut cards = vec![
+            new_card("a1"),
+            new_card("a2"),
+            new_card("b1"),
+            new_card("new"),
+        ];
+        cards[0].content.prefix = "a.md".to_string();
+        cards[1].content.prefix = "a.md".to_string();
+        cards[2].content.prefix = "b.md".to_string();
+        let day = default_date();
+        for (i, card) in cards.iter_mut().take(3).enumerate() {
+            card.fsrs_state
+                .review(ReviewAnswer::Good, &day, false, 1.0, &deck.params);
+            card.fsrs_state.review_date = day.checked_add_days(i as i32).unwrap();
+        }
+        let _ = deck.replace_cards(CardCollection::from(cards).unwrap());
+        let mut generator = SplitMix64::from_seed(42);
+        let review_day = day.checked_add_days(10).unwrap();
+        let fronts = |deck: &mut Deck, generator: &mut SplitMix64| {
+            deck.start_review(review_day, generator);
+            let mut fronts = vec![];
+            while let Some(card) = deck.get_review_card() {
+                fronts.push(card.content.front.clone());
+                deck.review_card(ReviewAnswer::Good, generator);
+            }
+            for card in deck.cards.iter_mut() {
+                card.fsrs_state.review_date = day;
+            }
+            deck.cards[3].fsrs_state = FSRSState::new(day);
+            fronts
+        };
+
+        deck.review_order = ReviewOrder::InterleavedByPrefix;
+        deck.new_card_position = NewCardPosition::First;
+        assert_eq!(fronts(&mut deck, &mut generator), ["new", "a1", "b1", "a2"]);
+        deck.review_order = ReviewOrder::GroupedByFile;
+        deck.new_card_position = NewCardPosition::Last;
+        assert_eq!(fronts(&mut deck, &mut generator), ["a1", "a2", "b1", "new"]);
+    }
+
+    #[test]
+    fn daily_limits_work() {
+        for track_review_history in [true, false] {
+            let mut deck = Deck::new();
+            deck.track_review_history = track_review_history;
+            deck.new_cards_per_day = Some(3);
+            deck.reviews_per_day = Some(1);
+            deck.new_card_position = NewCardPosition::First;
+            let mut cards: Vec<Card> = (0..10).map(|x| new_card(&format!("{}", x))).collect();
+            let yesterday = default_date().checked_add_days(-1).unwrap();
+            for card in cards.iter_mut().take(2) {
+                card.fsrs_state
+                    .review(ReviewAnswer::Good, &yesterday, true, 1.0, &deck.params);
+                card.fsrs_state.review_date = default_date();
+            }
+            let _ = deck.replace_cards(CardCollection::from(cards).unwrap());
+            let mut generator = SplitMix64::from_seed(42);
+
+            assert_eq!(deck.due_counts(default_date()), (3, 1));
+            deck.start_review(default_date(), &mut generator);
+            deck.review_card(ReviewAnswer::Easy, &mut generator);
+            deck.review_card(ReviewAnswer::Easy, &mut generator);
+            deck.stop_review();
+            // Reviews done today count against the limits
+            assert_eq!(deck.due_counts(default_date()), (1, 1));
+
+            deck.start_review(default_date(), &mut generator);
+            for _ in 0..2 {
+                deck.review_card(ReviewAnswer::Easy, &mut generator);
+            }
+            deck.stop_review();
+            assert_eq!(deck.due_counts(default_date()), (0, 0));
+        }
+    }
+
+    #[test]
+    fn forgotten_cards_stay_over_the_review_limit() {
+        let mut deck = Deck::new();
+        deck.reviews_per_day = Some(1);
+        let mut cards: Vec<Card> = (0..2).map(|x| new_card(&format!("{}", x))).collect();
+        let yesterday = default_date().checked_add_days(-1).unwrap();
+        for card in cards.iter_mut() {
+            card.fsrs_state
+                .review(ReviewAnswer::Good, &yesterday, true, 1.0, &deck.params);
+            card.fsrs_state.review_date = default_date();
+        }
+        let _ = deck.replace_cards(CardCollection::from(cards).unwrap());
+        let mut generator = SplitMix64::from_seed(42);
+
+        deck.start_review(default_date(), &mut generator);
+        assert_eq!(deck.active_review_count(), 1);
+        let front = deck.get_review_card().unwrap().content.front.clone();
+        deck.review_card(ReviewAnswer::Again, &mut generator);
+        deck.stop_review();
+
+        // The limit is used up, still the forgotten card is shown until it is answered Good
+        deck.start_review(default_date(), &mut generator);
+        assert_eq!(deck.active_review_count(), 1);
+        assert_eq!(deck.get_review_card().unwrap().content.front, front);
+    }
+
+    #[test]
+    fn siblings_are_deferred() {
+        let mut deck = Deck::new();
+        deck.bury_new_siblings = true;
+        deck.bury_review_siblings = true;
+        let cards = vec![
+            new_card_with_back("front1", "{{{back1}}} {{{back2}}} {{{back3}}} {{{back4}}}"),
+            new_card("other"),
+        ];
+        let _ = deck.replace_cards(CardCollection::from(cards).unwrap());
+        let cloze = |deck: &Deck, index: usize| {
+            deck.cards
+                .iter()
+                .position(|x| x.content.cloze_index == Some(index))
+                .unwrap()
+        };
+        let select = |deck: &mut Deck, card_index: usize| {
+            deck.review_index = deck.review_indices.iter().position(|x| *x == card_index);
+        };
+
+        // The last cloze has been reviewed and isn't due yet
+        let params = deck.params.clone();
+        let reviewed = cloze(&deck, 3);
+        let state = &mut deck.cards[reviewed].fsrs_state;
+        let ten_days_ago = default_date().checked_add_days(-10).unwrap();
+        state.review(ReviewAnswer::Good, &ten_days_ago, true, 1.0, &params);
+        state.review_date = default_date().checked_add_days(5).unwrap();
+
+        let mut generator = SplitMix64::from_seed(42);
+        deck.start_all_review(default_date(), &mut generator);
+        assert_eq!(deck.active_review_count(), 5);
+
+        // Forgotten cards don't defer their siblings
+        let index = cloze(&deck, 0);
+        select(&mut deck, index);
+        deck.review_card(ReviewAnswer::Again, &mut generator);
+        assert_eq!(deck.active_review_count(), 5);
+
+        let index = cloze(&deck, 1);
+        select(&mut deck, index);
+        deck.review_card(ReviewAnswer::Good, &mut generator);
+        let day = |deck: &Deck, index: usize| {
+            deck.cards[cloze(deck, index)].fsrs_state.review_date.day - default_date().day
+        };
+        let mut deferred = vec![day(&deck, 0), day(&deck, 2)];
+        deferred.sort();
+        assert_eq!(deferred, vec![1, 2]);
+        assert_eq!(day(&deck, 3), 5);
+        assert_eq!(deck.active_review_count(), 2);
+        assert!(deck.review_indices.contains(&cloze(&deck, 3)));
+    }
+
     #[test]
     fn getting_review_cards_works() {
         let vec = vec![
@@ -706,4 +2251,168 @@ mod tests {
             Editable::NotEditable
         );
     }
+
+    #[test]
+    fn cards_differing_by_tags_are_duplicates() {
+        let cards = crate::parsing::parse_cards("foo #a:: x\nfoo #b:: y\n", default_date(), None);
+        assert_eq!(cards[0].content.key(), cards[1].content.key());
+
+        let mut deck: Deck = Deck::new();
+        let result = deck.replace_cards(CardCollection::from(cards).unwrap());
+        assert!(result.is_ok());
+        assert_eq!(deck.cards.len(), 1);
+        assert_eq!(deck.cards[0].content.back, "x");
+        assert_eq!(
+            deck.cards[0].content.get_editability(),
+            Editable::NotEditable
+        );
+    }
+
+    #[test]
+    fn cards_are_matched_by_id() {
+        let mut deck: Deck = Deck::new();
+        deck.card_ids = CardIds::Block;
+        let _ = deck.replace_cards(CardCollection::from(vec![new_card("typo")]).unwrap());
+        assert_eq!(deck.edited_cards.len(), 1);
+        let id = deck.cards[0].content.id.clone().unwrap();
+        assert!(id.value().starts_with("tm-"));
+        deck.cards[0].fsrs_state.stability = 10.0;
+
+        // Fixing the front keeps the state as the id stays the same
+        let mut fixed = new_card("fixed");
+        fixed.content.id = Some(id.clone());
+        let _ = deck.replace_cards(CardCollection::from(vec![fixed, new_card("typo")]).unwrap());
+        assert_eq!(deck.orphans.len(), 0);
+        let fixed = deck.cards.iter().find(|x| x.content.front == "fixed");
+        assert_eq!(fixed.unwrap().fsrs_state.stability, 10.0);
+        let other = deck
+            .cards
+            .iter()
+            .find(|x| x.content.front == "typo")
+            .unwrap();
+        assert!(other.content.id.is_some());
+        assert_ne!(other.content.id, Some(id));
+    }
+
+    #[test]
+    fn pasted_ids_are_renewed() {
+        let mut deck: Deck = Deck::new();
+        deck.card_ids = CardIds::Block;
+        let _ = deck.replace_cards(CardCollection::from(vec![new_card("original")]).unwrap());
+        let id = deck.cards[0].content.id.clone().unwrap();
+        deck.cards[0].fsrs_state.stability = 10.0;
+        deck.edited_cards.clear();
+
+        // The copy is above the original, still the original keeps the id and its state
+        let mut copy = new_card("copy");
+        copy.content.id = Some(id.clone());
+        let mut original = new_card("original");
+        original.content.id = Some(id.clone());
+        let _ = deck.replace_cards(CardCollection::from(vec![copy, original]).unwrap());
+        let find = |front: &str| {
+            deck.cards
+                .iter()
+                .find(|x| x.content.front == front)
+                .unwrap()
+        };
+        assert_eq!(find("original").content.id, Some(id.clone()));
+        assert_eq!(find("original").fsrs_state.stability, 10.0);
+        assert!(find("copy").fsrs_state.is_new());
+        assert_ne!(find("copy").content.id, Some(id.clone()));
+        assert_eq!(deck.edited_cards.len(), 1);
+        assert_eq!(deck.edited_cards[0].0.content.id, Some(id));
+        assert_eq!(deck.edited_cards[0].1.content.id, find("copy").content.id);
+    }
+
+    #[test]
+    fn orphans_are_relinked() {
+        let mut cards = vec![new_card("to eat"), new_card("the house")];
+        cards[0].content.back = "comer".to_string();
+        cards[1].content.back = "casa".to_string();
+        let mut deck: Deck = Deck::new();
+        let _ = deck.replace_cards(CardCollection::from(cards.clone()).unwrap());
+        for card in deck.cards.iter_mut() {
+            card.fsrs_state.stability = 5.0;
+        }
+
+        // A small fix is relinked right away, a bigger change has to be confirmed
+        cards[0].content.front = "to eat (verb)".to_string();
+        cards[1].content.front = "the house.".to_string();
+        let _ = deck.replace_cards(CardCollection::from(cards).unwrap());
+        assert_eq!(deck.orphans.len(), 1);
+        assert_eq!(deck.relink_proposals.len(), 1);
+        let house = deck.cards.iter().find(|x| x.content.front == "the house.");
+        assert!(!house.unwrap().fsrs_state.is_new());
+
+        let card = deck.relink_proposals[0].card;
+        assert_eq!(deck.cards[card].content.front, "to eat (verb)");
+        deck.accept_relink(0);
+        assert!(deck.orphans.is_empty());
+        assert!(!deck.cards[card].fsrs_state.is_new());
+    }
+
+    #[test]
+    fn relink_proposals_are_made_again_after_loading() {
+        let cards = vec![new_card_with_back("to eat", "comer")];
+        let mut deck: Deck = Deck::new();
+        let _ = deck.replace_cards(CardCollection::from(cards).unwrap());
+        deck.cards[0].fsrs_state.stability = 5.0;
+        let cards = vec![
+            new_card_with_back("to eat (verb)", "comer"),
+            new_card_with_back("to drink", "beber"),
+        ];
+        let _ = deck.replace_cards(CardCollection::from(cards.clone()).unwrap());
+        assert_eq!(deck.relink_proposals.len(), 1);
+
+        // The deck is saved after updating and loaded again by the TUI
+        let saved = serde_json::to_string(&deck).unwrap();
+        let mut deck: Deck = serde_json::from_str(&saved).unwrap();
+        assert!(deck.relink_proposals.is_empty());
+        let _ = deck.replace_cards(CardCollection::from(cards.clone()).unwrap());
+        assert_eq!(deck.relink_proposals.len(), 1);
+        let card = deck.relink_proposals[0].card;
+        assert_eq!(deck.cards[card].content.front, "to eat (verb)");
+
+        // Rejected pairings stay rejected
+        deck.reject_relink(0);
+        let saved = serde_json::to_string(&deck).unwrap();
+        let mut deck: Deck = serde_json::from_str(&saved).unwrap();
+        let _ = deck.replace_cards(CardCollection::from(cards).unwrap());
+        assert!(deck.relink_proposals.is_empty());
+        assert_eq!(deck.orphans.len(), 1);
+    }
+
+    #[test]
+    fn moved_files_keep_their_cards() {
+        let file_card = |prefix: &str, front: &str, back: &str| {
+            let mut card = new_card(front);
+            card.content.prefix = prefix.to_string();
+            card.content.back = back.to_string();
+            card
+        };
+        let cards = vec![
+            file_card("old.md > A", "front1", "back1"),
+            file_card("old.md > B", "front2", "back2"),
+            file_card("other.md", "front1", "other"),
+        ];
+        let mut deck: Deck = Deck::new();
+        let _ = deck.replace_cards(CardCollection::from(cards).unwrap());
+        for card in deck.cards.iter_mut() {
+            card.fsrs_state.stability = 5.0;
+        }
+
+        let cards = vec![
+            file_card("new.md > A", "front1", "back1"),
+            file_card("new.md > B", "front2", "changed"),
+            file_card("other.md", "front1", "other"),
+        ];
+        let _ = deck.replace_cards(CardCollection::from(cards).unwrap());
+        assert_eq!(
+            deck.moved_files,
+            vec![("old.md".to_string(), "new.md".to_string())]
+        );
+        assert!(deck.orphans.is_empty());
+        assert!(deck.cards.iter().all(|x| !x.fsrs_state.is_new()));
+        assert!(deck.cards.iter().any(|x| x.content.back == "changed"));
+    }
 }
@@ -1,5 +1,5 @@
 use crate::{date::Date, rand::SplitMix64};
-use serde::{de::Error, Deserialize, Serialize};
+use serde::{Deserialize, Serialize};
 use std::f64::consts::E;
 
 #[derive(Debug, Deserialize, Clone, Serialize)]
@@ -9,16 +9,94 @@ pub struct FSRSState {
     pub review_date: Date,
     pub difficulty: f64,
     pub stability: f64,
-    pub buried: bool,
+    /// Buried cards are hidden until this day
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub buried_until: Option<Date>,
+    // Older decks had a single buried flag which meant suspension
+    #[serde(default, alias = "buried")]
+    pub suspended: bool,
+    /// Suspension ends on this day, suspended indefinitely if not set
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub suspended_until: Option<Date>,
     pub complete_history: bool,
     #[serde(default)]
     pub review_log: Vec<ReviewLogItem>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub learning_step: Option<LearningStep>,
+    /// UTC timestamp in milliseconds when the current learning step is due
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub step_due: Option<i64>,
+    /// Set when the card has been forgotten too many times
+    #[serde(default)]
+    pub leech: bool,
+    /// Day of the first review, counted for the daily limits when the review history isn't tracked
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub introduced: Option<Date>,
+}
+
+/// Index of the learning step a new card or a lapsed card is currently on
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub enum LearningStep {
+    Learning(usize),
+    Relearning(usize),
 }
 
-#[derive(Clone, Debug, PartialEq)]
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+#[serde(try_from = "StoredReviewLogItem")]
 pub struct ReviewLogItem {
     pub answer: ReviewAnswer,
     pub day: Date,
+    /// UTC time of the review in milliseconds since the unix epoch, missing from migrated logs
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timestamp: Option<i64>,
+    /// Milliseconds from showing the front of the card to answering it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub duration: Option<u32>,
+}
+
+#[derive(Deserialize)]
+struct ReviewLogFields {
+    answer: ReviewAnswer,
+    day: Date,
+    #[serde(default)]
+    timestamp: Option<i64>,
+    #[serde(default)]
+    duration: Option<u32>,
+}
+
+// Logs saved before timestamps packed the answer and the day into a single integer
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum StoredReviewLogItem {
+    Packed(i64),
+    Fields(ReviewLogFields),
+}
+
+impl TryFrom<StoredReviewLogItem> for ReviewLogItem {
+    type Error = String;
+
+    fn try_from(value: StoredReviewLogItem) -> Result<Self, Self::Error> {
+        match value {
+            StoredReviewLogItem::Packed(packed) => ReviewLogItem::from(packed).map_err(|_| {
+                format!(
+                    "Invalid review log entry {}, run tmemo migrate to remove it",
+                    packed
+                )
+            }),
+            StoredReviewLogItem::Fields(fields) => Ok(ReviewLogItem {
+                answer: fields.answer,
+                day: fields.day,
+                timestamp: fields.timestamp,
+                duration: fields.duration,
+            }),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Serialize)]
+pub struct ReviewTiming {
+    pub timestamp: i64,
+    pub duration: u32,
 }
 
 impl ReviewLogItem {
@@ -37,6 +115,8 @@ impl ReviewLogItem {
         Ok(ReviewLogItem {
             day,
             answer: answer_val,
+            timestamp: None,
+            duration: None,
         })
     }
 
@@ -52,46 +132,6 @@ impl ReviewLogItem {
     }
 }
 
-impl Serialize for ReviewLogItem {
-    fn serialize<S>(&self, serializer: S) -> Result<S::Ok, S::Error>
-    where
-        S: serde::Serializer,
-    {
-        let value: i64 = self.encode();
-        serializer.serialize_i64(value)
-    }
-}
-
-struct I64Visitor;
-impl serde::de::Visitor<'_> for I64Visitor {
-    type Value = i64;
-    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
-        formatter.write_str("Only numeric strings or i64 can be deserialised to i64")
-    }
-    fn visit_i64<E>(self, v: i64) -> Result<Self::Value, E>
-    where
-        E: Error,
-    {
-        Ok(v)
-    }
-    fn visit_u64<E>(self, v: u64) -> Result<Self::Value, E>
-    where
-        E: Error,
-    {
-        Ok(v as i64)
-    }
-}
-
-impl<'de> Deserialize<'de> for ReviewLogItem {
-    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
-    where
-        D: serde::Deserializer<'de>,
-    {
-        let value: i64 = deserializer.deserialize_i64(I64Visitor)?;
-        Ok(ReviewLogItem::from(value).unwrap())
-    }
-}
-
 #[derive(Clone, Deserialize, Debug, PartialEq, Serialize)]
 pub enum ReviewAnswer {
     Again,
@@ -107,18 +147,35 @@ pub enum ReviewResult {
     Discard,
 }
 
-const FACTOR: f64 = 19.0 / 81.0;
-const INV_DECAY: f64 = 1.0 / DECAY;
 const DECAY: f64 = -0.5;
 const DEFAULT_W: [f64; 17] = [
     0.5701, 1.4436, 4.1386, 10.9355, 5.1443, 1.2006, 0.8627, 0.0362, 1.629, 0.1342, 1.0166, 2.1174,
     0.0839, 0.3204, 1.4676, 0.219, 2.8237,
 ];
+const DEFAULT_W5: [f64; 19] = [
+    0.40255, 1.18385, 3.173, 15.69105, 7.1949, 0.5345, 1.4604, 0.0046, 1.54575, 0.1192, 1.01925,
+    1.9395, 0.11, 0.29605, 2.2698, 0.2315, 2.9898, 0.51655, 0.6621,
+];
+const DEFAULT_W6: [f64; 21] = [
+    0.212, 1.2931, 2.3065, 8.2956, 6.4133, 0.8334, 3.0194, 0.001, 1.8722, 0.1666, 0.796, 1.4835,
+    0.0614, 0.2629, 1.6483, 0.6014, 1.8729, 0.5425, 0.0912, 0.0658, 0.1542,
+];
 const RANDOMNESS: f64 = 0.1; // Determines the range [1.0-RANDOMNESS, 1.0+RANDOMNESS] where the next review will land
 
+/// Version of the FSRS model, decks without a version in their params are on v4
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Serialize)]
+pub enum FSRSVersion {
+    #[default]
+    V4,
+    V5,
+    V6,
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct FSRSParams {
-    pub w: [f64; 17],
+    #[serde(default)]
+    pub version: FSRSVersion,
+    pub w: Vec<f64>,
     pub target_retention: f64,
 }
 
@@ -130,16 +187,60 @@ impl Default for FSRSParams {
 
 impl FSRSParams {
     pub fn new() -> FSRSParams {
+        FSRSParams::from_version(FSRSVersion::V6)
+    }
+
+    pub fn from_version(version: FSRSVersion) -> FSRSParams {
+        let w = match version {
+            FSRSVersion::V4 => DEFAULT_W.to_vec(),
+            FSRSVersion::V5 => DEFAULT_W5.to_vec(),
+            FSRSVersion::V6 => DEFAULT_W6.to_vec(),
+        };
         FSRSParams {
-            w: DEFAULT_W,
+            version,
+            w,
             target_retention: 0.9,
         }
     }
+
+    pub fn decay(&self) -> f64 {
+        match self.version {
+            FSRSVersion::V6 => -self.w[20],
+            _ => DECAY,
+        }
+    }
+
+    /// Stability for which the interval at the target retention is the given number of days
+    pub fn stability_for_interval(&self, interval: f64) -> f64 {
+        let decay = self.decay();
+        interval * factor(decay) / (self.target_retention.powf(1.0 / decay) - 1.0)
+    }
+}
+
+// Chosen so that the retention is 90% when the elapsed time equals the stability
+fn factor(decay: f64) -> f64 {
+    0.9_f64.powf(1.0 / decay) - 1.0
+}
+
+fn initial_difficulty(g: f64, params: &FSRSParams) -> f64 {
+    match params.version {
+        FSRSVersion::V4 => params.w[4] - (g - 3.0) * params.w[5],
+        _ => (params.w[4] - (params.w[5] * (g - 1.0)).exp() + 1.0).clamp(1.0, 10.0),
+    }
 }
 
 fn new_difficulty(d: f64, g: f64, params: &FSRSParams) -> f64 {
-    let mut new_d = d - params.w[6] * (g - 3.0);
-    new_d = params.w[7] * (params.w[4] - new_d) + new_d;
+    let new_d = match params.version {
+        FSRSVersion::V4 => {
+            let new_d = d - params.w[6] * (g - 3.0);
+            params.w[7] * (params.w[4] - new_d) + new_d
+        }
+        _ => {
+            // Linear damping so that the difficulty approaches 10 slower
+            let damped = d - params.w[6] * (g - 3.0) * (10.0 - d) / 9.0;
+            params.w[7] * initial_difficulty(4.0, params) + (1.0 - params.w[7]) * damped
+        }
+    };
     new_d.clamp(1.0, 10.0)
 }
 
@@ -177,10 +278,31 @@ fn new_stability_incorrect(
     retention: f64,
     params: &FSRSParams,
 ) -> f64 {
-    params.w[11]
+    let new_stability = params.w[11]
         * difficulty.powf(-params.w[12])
         * ((stability + 1.0).powf(params.w[13]) - 1.0)
-        * E.powf(params.w[14] * (1.0 - retention))
+        * E.powf(params.w[14] * (1.0 - retention));
+
+    match params.version {
+        FSRSVersion::V4 => new_stability,
+        _ => new_stability.min(stability / E.powf(params.w[17] * params.w[18])),
+    }
+}
+
+// Stability after a review on the same day as the previous one, not used in v4
+fn new_stability_short_term(stability: f64, g: f64, params: &FSRSParams) -> f64 {
+    let increase = E.powf(params.w[17] * (g - 3.0 + params.w[18]));
+    match params.version {
+        FSRSVersion::V6 => {
+            let increase = increase * stability.powf(-params.w[19]);
+            if g >= 3.0 {
+                stability * increase.max(1.0)
+            } else {
+                stability * increase
+            }
+        }
+        _ => stability * increase,
+    }
 }
 
 fn grade_f64(answer: ReviewAnswer) -> f64 {
@@ -193,8 +315,8 @@ fn grade_f64(answer: ReviewAnswer) -> f64 {
     }
 }
 
-fn power_forgetting_curve(delta_t: f64, stability: f64) -> f64 {
-    (1.0 + FACTOR * delta_t / stability).powf(DECAY)
+fn power_forgetting_curve(delta_t: f64, stability: f64, decay: f64) -> f64 {
+    (1.0 + factor(decay) * delta_t / stability).powf(decay)
 }
 
 impl FSRSState {
@@ -205,24 +327,78 @@ impl FSRSState {
             review_date: date,
             difficulty: 0.0,
             stability: 0.0,
-            buried: false,
+            buried_until: None,
+            suspended: false,
+            suspended_until: None,
             complete_history: true,
             review_log: vec![],
+            learning_step: None,
+            step_due: None,
+            leech: false,
+            introduced: None,
         }
     }
 
+    /// Card has never been reviewed
+    pub fn is_new(&self) -> bool {
+        self.stability == 0.0
+    }
+
     pub fn first_review(&self) -> bool {
         self.complete_history && self.review_log.is_empty()
     }
 
-    pub fn retention(&self, date: &Date) -> f64 {
+    /// Hides the card until the next day
+    pub fn bury(&mut self, date: &Date) {
+        self.buried_until = date.checked_add_days(1);
+    }
+
+    pub fn suspend(&mut self, until: Option<Date>) {
+        self.suspended = true;
+        self.suspended_until = until;
+    }
+
+    /// Clears both suspension and burying
+    pub fn unsuspend(&mut self) {
+        self.suspended = false;
+        self.suspended_until = None;
+        self.buried_until = None;
+    }
+
+    pub fn is_suspended(&self, date: &Date) -> bool {
+        match self.suspended_until {
+            Some(until) => self.suspended && date.day < until.day,
+            None => self.suspended,
+        }
+    }
+
+    /// Whether the card should be left out of reviews on the date
+    pub fn is_hidden(&self, date: &Date) -> bool {
+        let buried = match self.buried_until {
+            Some(until) => date.day < until.day,
+            None => false,
+        };
+        buried || self.is_suspended(date)
+    }
+
+    /// Number of times the card was forgotten, only the first answer of each day after the first review counts
+    pub fn lapses(&self) -> u32 {
+        let log = &self.review_log;
+        (1..log.len())
+            .filter(|i| log[*i].answer == ReviewAnswer::Again && log[*i - 1].day != log[*i].day)
+            .count() as u32
+    }
+
+    pub fn retention(&self, date: &Date, params: &FSRSParams) -> f64 {
         let mut t: f64 = (date.day - self.last_review.day).into();
         t = if t >= 1.0 { t } else { 1.0 };
-        power_forgetting_curve(t, self.stability)
+        power_forgetting_curve(t, self.stability, params.decay())
     }
 
     pub fn interval(&self, params: &FSRSParams) -> f64 {
-        (self.stability / FACTOR * (params.target_retention.powf(INV_DECAY) - 1.0)).max(1.0)
+        let decay = params.decay();
+        (self.stability / factor(decay) * (params.target_retention.powf(1.0 / decay) - 1.0))
+            .max(1.0)
     }
 
     fn update_review_success(&mut self, date: &Date, fraction: f64, params: &FSRSParams) {
@@ -243,32 +419,25 @@ impl FSRSState {
         fraction: f64,
         params: &FSRSParams,
     ) -> ReviewResult {
-        match answer {
-            ReviewAnswer::Easy => {
-                self.stability = params.w[3];
-                self.difficulty = params.w[4] - params.w[5];
-                self.update_review_success(date, fraction, params);
-                ReviewResult::Discard
-            }
-            ReviewAnswer::Good => {
-                self.stability = params.w[2];
-                self.difficulty = params.w[4];
-                self.update_review_success(date, fraction, params);
-                ReviewResult::Discard
-            }
-            ReviewAnswer::Hard => {
-                self.stability = params.w[1];
-                self.difficulty = params.w[4] + params.w[5];
-                self.update_review_success(date, fraction, params);
-                ReviewResult::Discard
-            }
-            ReviewAnswer::Again => {
-                self.stability = params.w[0];
-                self.difficulty = params.w[4] + params.w[5] * 2.0;
-                self.update_review_failure(date);
-                ReviewResult::Again
-            }
-            ReviewAnswer::Bury => panic!("unexpected bury"),
+        let g = grade_f64(answer.clone());
+        self.introduced = Some(*date);
+        self.stability = params.w[g as usize - 1];
+        self.difficulty = initial_difficulty(g, params);
+
+        if let ReviewAnswer::Again = answer {
+            self.update_review_failure(date);
+            ReviewResult::Again
+        } else {
+            self.update_review_success(date, fraction, params);
+            ReviewResult::Discard
+        }
+    }
+
+    /// Attaches the time and duration of the review to the latest review log entry
+    pub fn record_timing(&mut self, timing: &ReviewTiming) {
+        if let Some(item) = self.review_log.last_mut() {
+            item.timestamp = Some(timing.timestamp);
+            item.duration = Some(timing.duration);
         }
     }
 
@@ -317,7 +486,7 @@ impl FSRSState {
         params: &FSRSParams,
     ) -> ReviewResult {
         if let ReviewAnswer::Bury = answer {
-            self.buried = true;
+            self.bury(date);
             return ReviewResult::Discard;
         }
         let first_review = self.first_review();
@@ -326,6 +495,8 @@ impl FSRSState {
             self.review_log.push(ReviewLogItem {
                 answer: answer.clone(),
                 day: *date,
+                timestamp: None,
+                duration: None,
             });
         } else {
             self.complete_history = false;
@@ -336,15 +507,28 @@ impl FSRSState {
             return result;
         }
 
+        if params.version != FSRSVersion::V4 && *date == self.last_review {
+            let g = grade_f64(answer.clone());
+            self.stability = new_stability_short_term(self.stability, g, params);
+            self.difficulty = new_difficulty(self.difficulty, g, params);
+            if let ReviewAnswer::Again = answer {
+                self.update_review_failure(date);
+                return ReviewResult::Again;
+            } else {
+                self.update_review_success(date, fraction, params);
+                return ReviewResult::Discard;
+            }
+        }
+
         if let ReviewAnswer::Again = answer {
-            let retention = self.retention(date);
+            let retention = self.retention(date, params);
             self.stability =
                 new_stability_incorrect(self.difficulty, self.stability, retention, params);
             self.difficulty = new_difficulty(self.difficulty, grade_f64(answer), params);
             self.update_review_failure(date);
             ReviewResult::Again
         } else {
-            let retention = self.retention(date);
+            let retention = self.retention(date, params);
             self.stability = new_stability_correct(
                 self.difficulty,
                 self.stability,
@@ -362,7 +546,11 @@ impl FSRSState {
 #[cfg(test)]
 mod tests {
     use super::FSRSParams;
+    use super::FSRSVersion;
     use super::ReviewLogItem;
+    use super::ReviewResult;
+    use super::ReviewTiming;
+    use super::DECAY;
     use crate::date::Date;
     use crate::fsrs::new_difficulty;
     use crate::fsrs::new_stability_incorrect;
@@ -382,7 +570,7 @@ mod tests {
     }
 
     fn schedule_tester2(reviews: Vec<ReviewItem>, expected_result: i32) {
-        let params = FSRSParams::new();
+        let params = FSRSParams::from_version(FSRSVersion::V4);
         let mut state = FSRSState::new(default_date());
         let mut current_date = default_date();
 
@@ -396,7 +584,7 @@ mod tests {
     }
 
     fn schedule_tester(reviews: Vec<ReviewAnswer>, expected_results: Vec<i32>) {
-        let params = FSRSParams::new();
+        let params = FSRSParams::from_version(FSRSVersion::V4);
         let mut state = FSRSState::new(default_date());
         let mut current_date = default_date();
 
@@ -410,7 +598,7 @@ mod tests {
 
     #[test]
     fn random_tester() {
-        let params = FSRSParams::new();
+        let params = FSRSParams::from_version(FSRSVersion::V4);
         let mut date = default_date();
         let mut state = FSRSState::new(date);
         let mut rng = SplitMix64::from_seed(3);
@@ -473,14 +661,14 @@ mod tests {
         let stability = [1.0, 2.0, 3.0, 4.0, 4.0, 2.0];
         let expected = [1.0, 0.946059, 0.9299294, 0.9221679, 0.9, 0.79394597];
         for i in 0..5 {
-            let retention = power_forgetting_curve(delta_t[i], stability[i]);
+            let retention = power_forgetting_curve(delta_t[i], stability[i], DECAY);
             assert!((retention - expected[i]).abs() < 1e-5);
         }
     }
 
     #[test]
     fn difficulty_works() {
-        let params = FSRSParams::new();
+        let params = FSRSParams::from_version(FSRSVersion::V4);
         let difficulties = [5.0; 4];
         let answers = [1.0, 2.0, 3.0, 4.0];
         let new_difficulties = [6.6681643, 5.836694, 5.0052238, 4.1737533];
@@ -492,7 +680,7 @@ mod tests {
 
     #[test]
     fn stability_works() {
-        let params = FSRSParams::new();
+        let params = FSRSParams::from_version(FSRSVersion::V4);
         let stabilities = [5.0; 4];
         let difficulties = [1.0, 2.0, 3.0, 4.0];
         let retentions = [0.9, 0.8, 0.7, 0.6];
@@ -508,7 +696,7 @@ mod tests {
     fn retention_works() {
         let date = Date { day: 1000 };
         let mut state = FSRSState::new(date);
-        let mut params = FSRSParams::new();
+        let mut params = FSRSParams::from_version(FSRSVersion::V4);
         params.target_retention = 0.95;
         state.review(ReviewAnswer::Good, &date, false, 1.0, &params);
         assert_eq!(2, state.review_date.day - date.day);
@@ -523,6 +711,54 @@ mod tests {
         assert_eq!(18, state.review_date.day - review_day.day);
     }
 
+    #[test]
+    fn forgetting_curve_is_ninety_percent_at_stability() {
+        for decay in [-0.1, -0.1542, -0.5, -0.8] {
+            for stability in [0.5, 3.0, 40.0] {
+                let retention = power_forgetting_curve(stability, stability, decay);
+                assert!((retention - 0.9).abs() < 1e-9);
+            }
+        }
+    }
+
+    #[test]
+    fn newer_versions_use_learnable_decay() {
+        let v4 = FSRSParams::from_version(FSRSVersion::V4);
+        let v5 = FSRSParams::from_version(FSRSVersion::V5);
+        let mut v6 = FSRSParams::from_version(FSRSVersion::V6);
+        assert_eq!(v4.decay(), -0.5);
+        assert_eq!(v5.decay(), -0.5);
+        assert_eq!(v6.decay(), -0.1542);
+        v6.w[20] = 0.3;
+        assert_eq!(v6.decay(), -0.3);
+    }
+
+    #[test]
+    fn same_day_reviews_change_stability() {
+        for version in [FSRSVersion::V5, FSRSVersion::V6] {
+            let params = FSRSParams::from_version(version);
+            let date = default_date();
+            let mut state = FSRSState::new(date);
+            let result = state.review(ReviewAnswer::Again, &date, false, 1.0, &params);
+            assert_eq!(result, ReviewResult::Again);
+            let stability_after_again = state.stability;
+            let result = state.review(ReviewAnswer::Good, &date, false, 1.0, &params);
+            assert_eq!(result, ReviewResult::Discard);
+            assert!(state.stability > stability_after_again);
+            assert!(state.review_date.day > date.day);
+        }
+    }
+
+    #[test]
+    fn initial_difficulty_is_clamped_in_newer_versions() {
+        let params = FSRSParams::from_version(FSRSVersion::V6);
+        for g in [1.0, 2.0, 3.0, 4.0] {
+            let difficulty = super::initial_difficulty(g, &params);
+            assert!((1.0..=10.0).contains(&difficulty));
+        }
+        assert!(super::initial_difficulty(1.0, &params) > super::initial_difficulty(4.0, &params));
+    }
+
     #[test]
     fn review_log_serialization_works() {
         for i in 1000..2000 {
@@ -536,6 +772,8 @@ mod tests {
                 let item = ReviewLogItem {
                     day: Date { day: i },
                     answer: answer.clone(),
+                    timestamp: None,
+                    duration: None,
                 };
                 let encoded = item.encode();
                 let decoded = ReviewLogItem::from(encoded).unwrap();
@@ -543,4 +781,84 @@ mod tests {
             }
         }
     }
+
+    #[test]
+    fn packed_review_logs_are_read() {
+        let item = ReviewLogItem {
+            day: Date { day: 1000 },
+            answer: ReviewAnswer::Hard,
+            timestamp: None,
+            duration: None,
+        };
+        let json = format!(
+            "[{}, {}]",
+            item.encode(),
+            serde_json::to_string(&item).unwrap()
+        );
+        let deserialized: Vec<ReviewLogItem> = serde_json::from_str(&json).unwrap();
+        assert_eq!(deserialized, vec![item.clone(), item]);
+        let invalid = format!("[{}]", 9i64 << 32);
+        assert!(serde_json::from_str::<Vec<ReviewLogItem>>(&invalid).is_err());
+    }
+
+    #[test]
+    fn lapses_are_counted_once_per_day() {
+        let params = FSRSParams::new();
+        let mut state = FSRSState::new(default_date());
+        let mut date = default_date();
+        state.review(ReviewAnswer::Again, &date, true, 1.0, &params);
+        state.review(ReviewAnswer::Good, &date, true, 1.0, &params);
+        for _ in 0..3 {
+            date = state.review_date;
+            state.review(ReviewAnswer::Again, &date, true, 1.0, &params);
+            state.review(ReviewAnswer::Again, &date, true, 1.0, &params);
+            state.review(ReviewAnswer::Good, &date, true, 1.0, &params);
+        }
+        assert_eq!(state.lapses(), 3);
+    }
+
+    #[test]
+    fn burying_and_suspension_expire() {
+        let params = FSRSParams::new();
+        let date = default_date();
+        let mut state = FSRSState::new(date);
+        state.review(ReviewAnswer::Bury, &date, true, 1.0, &params);
+        assert!(state.is_hidden(&date));
+        assert!(!state.is_hidden(&date.checked_add_days(1).unwrap()));
+
+        state.suspend(date.checked_add_days(10));
+        assert!(state.is_hidden(&date.checked_add_days(9).unwrap()));
+        assert!(!state.is_hidden(&date.checked_add_days(10).unwrap()));
+        state.suspend(None);
+        assert!(state.is_hidden(&date.checked_add_days(1000).unwrap()));
+        state.unsuspend();
+        assert!(!state.is_hidden(&date));
+    }
+
+    #[test]
+    fn legacy_buried_flag_is_suspension() {
+        let mut value = serde_json::to_value(FSRSState::new(default_date())).unwrap();
+        let object = value.as_object_mut().unwrap();
+        object.remove("suspended");
+        object.insert("buried".to_string(), serde_json::Value::Bool(true));
+        let state: FSRSState = serde_json::from_value(value).unwrap();
+        assert!(state.suspended);
+    }
+
+    #[test]
+    fn timed_review_log_serialization_works() {
+        let date = Date { day: 1000 };
+        let mut state = FSRSState::new(date);
+        let params = FSRSParams::new();
+        state.review(ReviewAnswer::Good, &date, true, 1.0, &params);
+        state.record_timing(&ReviewTiming {
+            timestamp: 1712345678000,
+            duration: 4200,
+        });
+        let serialized = serde_json::to_string(&state.review_log).unwrap();
+        let deserialized: Vec<ReviewLogItem> = serde_json::from_str(&serialized).unwrap();
+        assert_eq!(deserialized, state.review_log);
+        assert_eq!(deserialized[0].timestamp, Some(1712345678000));
+        assert_eq!(deserialized[0].duration, Some(4200));
+    }
 }
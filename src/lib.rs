@@ -1,3 +1,4 @@
+pub mod anki;
 pub mod card;
 pub mod cardcache;
 pub mod cmd;
@@ -5,7 +6,14 @@ pub mod date;
 pub mod deck;
 pub mod fsrs;
 pub mod migrations;
+pub mod obsidian;
+pub mod optimizer;
 pub mod parsing;
+pub mod preset;
 pub mod rand;
+pub mod relink;
 pub mod render;
+pub mod simulation;
 pub mod state;
+pub mod stats;
+pub mod tags;
@@ -1,4 +1,13 @@
+use crate::card::{card_key, CardCollection};
+use crate::cardcache::get_md_files_in_path;
+use crate::date::Date;
+use crate::fsrs::{FSRSParams, FSRSVersion, ReviewLogItem};
+use crate::obsidian;
+use crate::parsing;
 use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::ffi::OsStr;
 use std::io::{BufReader, BufWriter};
 
 fn migrate_add_version_number(value: &Value) -> Option<Value> {
@@ -54,6 +63,211 @@ fn migrate_version_2_to_3(value: &Value) -> Option<Value> {
     Some(output)
 }
 
+// Relative paths of the markdown files by file name, with the keys of the cards in each file
+fn files_by_name() -> HashMap<String, Vec<(String, HashSet<String>)>> {
+    let mut files: HashMap<String, Vec<(String, HashSet<String>)>> = HashMap::new();
+    let current_dir = env::current_dir().unwrap();
+    for file in get_md_files_in_path(OsStr::new(&current_dir)) {
+        let contents = parsing::read_to_string(&file.path);
+        let cards = parsing::parse_cards(&contents, Date::now(), Some(file.relative_path.clone()));
+        let keys = match CardCollection::from(cards) {
+            Ok(collection) => collection.cards.iter().map(|x| x.content.key()).collect(),
+            Err(_) => HashSet::new(),
+        };
+        files
+            .entry(file.string_filename)
+            .or_default()
+            .push((file.relative_path, keys));
+    }
+    files
+}
+
+// Replaces the file names at the start of the prefixes with the path of the file that has the card, returns the keys of the cards that were left as they are
+fn relabel_prefixes(
+    value: &mut Value,
+    files: &HashMap<String, Vec<(String, HashSet<String>)>>,
+) -> Vec<String> {
+    let mut ambiguous = vec![];
+    for key in ["cards", "orphans"] {
+        let cards = match value.get_mut(key).and_then(|x| x.as_array_mut()) {
+            Some(cards) => cards,
+            None => continue,
+        };
+        for card in cards {
+            let content = card.get_mut("content").unwrap();
+            let prefix = content.get("prefix").unwrap().as_str().unwrap().to_string();
+            let front = content.get("front").unwrap().as_str().unwrap().to_string();
+            let name = prefix.split('>').next().unwrap().trim_end();
+            let candidates = match files.get(name) {
+                Some(candidates) => candidates,
+                None => continue,
+            };
+
+            // Files with the same name are told apart by the cards in them
+            let rest = &prefix[name.len()..];
+            let found = candidates.iter().find(|(path, keys)| {
+                keys.contains(&card_key(&format!("{}{}", path, rest), &front))
+            });
+            let path = match (found, candidates.len()) {
+                (Some((path, _)), _) => path,
+                (None, 1) => &candidates[0].0,
+                (None, _) => {
+                    ambiguous.push(format!("{}{}", prefix, front));
+                    continue;
+                }
+            };
+            let new_prefix = format!("{}{}", path, rest);
+            *content.get_mut("prefix").unwrap() = Value::String(new_prefix);
+        }
+    }
+    ambiguous
+}
+
+fn migrate_version_3_to_4(value: &Value) -> Option<Value> {
+    let mut output = value.clone();
+    let version = output.get("parsing_version")?.as_u64().unwrap();
+    if version != 3 {
+        return None;
+    }
+    let ambiguous = relabel_prefixes(&mut output, &files_by_name());
+    if !ambiguous.is_empty() {
+        println!(
+            "These cards keep the file name as their prefix, several files have that name and none of them has the card:"
+        );
+        for key in &ambiguous {
+            println!("  {}", key);
+        }
+    }
+    let parsing_ver = output.get_mut("parsing_version")?;
+    *parsing_ver = serde_json::to_value(4u64).unwrap();
+
+    Some(output)
+}
+
+// Comments of the Obsidian Spaced Repetition plugin are no longer part of the card text
+fn migrate_version_4_to_5(value: &Value) -> Option<Value> {
+    let mut output = value.clone();
+    let version = output.get("parsing_version")?.as_u64().unwrap();
+    if version != 4 {
+        return None;
+    }
+    for key in ["cards", "orphans"] {
+        let cards = match output.get_mut(key).and_then(|x| x.as_array_mut()) {
+            Some(cards) => cards,
+            None => continue,
+        };
+        for content in cards.iter_mut().filter_map(|x| x.get_mut("content")) {
+            for field in ["front", "back"] {
+                if let Some(Value::String(text)) = content.get_mut(field) {
+                    *text = obsidian::strip_sr_comments(text);
+                }
+            }
+        }
+    }
+    let parsing_ver = output.get_mut("parsing_version")?;
+    *parsing_ver = serde_json::to_value(5u64).unwrap();
+
+    Some(output)
+}
+
+// Decks saved before the params had a version are on v4 and keep their weights
+fn migrate_fsrs_version(value: &Value) -> Option<Value> {
+    let mut output = value.clone();
+    let params = output.get_mut("params")?.as_object_mut()?;
+    if params.contains_key("version") {
+        return None;
+    }
+    params.insert(
+        "version".into(),
+        serde_json::to_value(FSRSVersion::V4).unwrap(),
+    );
+
+    Some(output)
+}
+
+// Replaces v4 params with the FSRS-6 defaults, keeping the target retention
+fn upgrade_params_to_v6(params: &mut Value) -> bool {
+    let version = params.get("version").cloned();
+    if version.is_some() && version != Some(serde_json::to_value(FSRSVersion::V4).unwrap()) {
+        return false;
+    }
+    let v4_defaults = serde_json::to_value(FSRSParams::from_version(FSRSVersion::V4).w).unwrap();
+    if params.get("w") != Some(&v4_defaults) {
+        println!("Custom FSRS v4 weights were replaced with FSRS-6 defaults, run tmemo optimize to fit them again");
+    }
+    let mut new_params = FSRSParams::from_version(FSRSVersion::V6);
+    if let Some(retention) = params.get("target_retention").and_then(|x| x.as_f64()) {
+        new_params.target_retention = retention;
+    }
+    *params = serde_json::to_value(new_params).unwrap();
+    true
+}
+
+fn upgrade_fsrs_v4_to_v6(value: &Value) -> Option<Value> {
+    let mut output = value.clone();
+    let mut upgraded = upgrade_params_to_v6(output.get_mut("params")?);
+    if let Some(presets) = output.get_mut("presets").and_then(|x| x.as_array_mut()) {
+        for preset in presets {
+            if let Some(params) = preset.get_mut("params") {
+                upgraded |= upgrade_params_to_v6(params);
+            }
+        }
+    }
+
+    match upgraded {
+        true => Some(output),
+        false => None,
+    }
+}
+
+// Loading reads the packed logs as well, this rewrites them in the current format
+fn migrate_packed_review_logs(value: &Value) -> Option<Value> {
+    let mut output = value.clone();
+    let mut migrated = false;
+    for key in ["cards", "orphans"] {
+        let cards = match output.get_mut(key).and_then(|x| x.as_array_mut()) {
+            Some(cards) => cards,
+            None => continue,
+        };
+        for card in cards {
+            let review_log = card
+                .get_mut("fsrs_state")
+                .and_then(|x| x.get_mut("review_log"))
+                .and_then(|x| x.as_array_mut());
+            let review_log = match review_log {
+                Some(review_log) => review_log,
+                None => continue,
+            };
+            if !review_log.iter().any(|x| x.is_i64()) {
+                continue;
+            }
+            migrated = true;
+            review_log.retain_mut(|item| {
+                let packed = match item.as_i64() {
+                    Some(packed) => packed,
+                    None => return true,
+                };
+                match ReviewLogItem::from(packed) {
+                    Ok(unpacked) => {
+                        *item = serde_json::to_value(unpacked).unwrap();
+                        true
+                    }
+                    Err(()) => {
+                        println!("Removed invalid review log entry {}", packed);
+                        false
+                    }
+                }
+            });
+        }
+    }
+
+    if migrated {
+        Some(output)
+    } else {
+        None
+    }
+}
+
 fn try_migrations(value: &Value) -> Option<Value> {
     if let Some(output) = migrate_add_version_number(value) {
         return Some(output);
@@ -61,6 +275,18 @@ fn try_migrations(value: &Value) -> Option<Value> {
     if let Some(output) = migrate_version_2_to_3(value) {
         return Some(output);
     }
+    if let Some(output) = migrate_version_3_to_4(value) {
+        return Some(output);
+    }
+    if let Some(output) = migrate_version_4_to_5(value) {
+        return Some(output);
+    }
+    if let Some(output) = migrate_fsrs_version(value) {
+        return Some(output);
+    }
+    if let Some(output) = migrate_packed_review_logs(value) {
+        return Some(output);
+    }
     None
 }
 
@@ -74,19 +300,155 @@ fn migrate(value: &mut Value) -> bool {
     migration_result
 }
 
-pub fn migrate_deck(path: String) -> std::result::Result<(), Box<dyn std::error::Error>> {
-    let mut value: Value;
-    {
-        let reader = BufReader::new(std::fs::File::open(path.clone())?);
-        value = serde_json::from_reader(reader).unwrap();
-    }
-    if !migrate(&mut value) {
-        return Err(String::from("No migration was done").into());
-    }
+fn read_deck_value(path: &str) -> std::result::Result<Value, Box<dyn std::error::Error>> {
+    let reader = BufReader::new(std::fs::File::open(path)?);
+    Ok(serde_json::from_reader(reader)?)
+}
+
+fn write_deck_value(
+    path: String,
+    value: &Value,
+) -> std::result::Result<(), Box<dyn std::error::Error>> {
     let mut tmp_path = path.clone();
     tmp_path.push_str(".temp");
     let writer = BufWriter::new(std::fs::File::create(tmp_path.clone())?);
-    serde_json::to_writer_pretty(writer, &value)?;
+    serde_json::to_writer_pretty(writer, value)?;
     std::fs::rename(tmp_path, path)?;
     Ok(())
 }
+
+pub fn migrate_deck(path: String) -> std::result::Result<(), Box<dyn std::error::Error>> {
+    let mut value = read_deck_value(&path)?;
+    if !migrate(&mut value) {
+        return Err(String::from("No migration was done").into());
+    }
+    write_deck_value(path, &value)
+}
+
+/// Moves the deck and its presets from FSRS v4 to FSRS-6, fitted v4 weights are replaced with the defaults
+pub fn upgrade_fsrs(path: String) -> std::result::Result<(), Box<dyn std::error::Error>> {
+    let mut value = read_deck_value(&path)?;
+    match upgrade_fsrs_v4_to_v6(&value) {
+        Some(upgraded) => value = upgraded,
+        None => return Err(String::from("The deck is already on a newer FSRS version").into()),
+    }
+    write_deck_value(path, &value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn v4_weights_survive_migration() {
+        let mut w = FSRSParams::from_version(FSRSVersion::V4).w;
+        w[0] = 1.234;
+        let mut value = json!({
+            "parsing_version": 4,
+            "cards": [],
+            "orphans": [],
+            "params": {"w": w, "target_retention": 0.85},
+        });
+        assert!(migrate(&mut value));
+        let params: FSRSParams = serde_json::from_value(value["params"].clone()).unwrap();
+        assert_eq!(params.version, FSRSVersion::V4);
+        assert_eq!(params.w, w);
+        assert!(!migrate(&mut value));
+
+        let upgraded = upgrade_fsrs_v4_to_v6(&value).unwrap();
+        let params: FSRSParams = serde_json::from_value(upgraded["params"].clone()).unwrap();
+        assert_eq!(params.version, FSRSVersion::V6);
+        assert_eq!(params.target_retention, 0.85);
+        assert!(upgrade_fsrs_v4_to_v6(&upgraded).is_none());
+    }
+
+    #[test]
+    fn sr_comments_are_migrated() {
+        let mut value = json!({
+            "parsing_version": 4,
+            "params": {"version": "V6", "w": [], "target_retention": 0.9},
+            "cards": [
+                {"content": {"prefix": "a.md", "front": "Spain", "back": "Madrid <!--SR:!2024-03-01,12,250-->"}},
+            ],
+            "orphans": [],
+        });
+        assert!(migrate(&mut value));
+        assert_eq!(value["parsing_version"], 5);
+        assert_eq!(value["cards"][0]["content"]["back"], "Madrid");
+    }
+
+    #[test]
+    fn packed_review_logs_are_migrated() {
+        let good = ReviewLogItem::from((3i64 << 32) | 1000).unwrap();
+        let mut value = json!({
+            "parsing_version": 4,
+            "params": {"version": "V4", "w": [], "target_retention": 0.9},
+            "cards": [
+                {"fsrs_state": {"review_log": [(3i64 << 32) | 1000, 9i64 << 32]}},
+                {"content": {}},
+            ],
+            "orphans": [],
+        });
+        assert!(migrate(&mut value));
+        let log: Vec<ReviewLogItem> =
+            serde_json::from_value(value["cards"][0]["fsrs_state"]["review_log"].clone()).unwrap();
+        assert_eq!(log, vec![good]);
+    }
+
+    #[test]
+    fn prefixes_are_relabeled() {
+        let mut value = json!({
+            "cards": [
+                {"content": {"prefix": "index.md > Verbs", "front": "ser"}},
+                {"content": {"prefix": "index.md", "front": "casa"}},
+                {"content": {"prefix": "index.md", "front": "unknown"}},
+                {"content": {"prefix": "notes.md", "front": "x"}},
+            ],
+            "orphans": [
+                {"content": {"prefix": "root.md", "front": "y"}},
+            ],
+        });
+        let mut files: HashMap<String, Vec<(String, HashSet<String>)>> = HashMap::new();
+        files.insert(
+            "index.md".to_string(),
+            vec![
+                (
+                    "spanish/index.md".to_string(),
+                    HashSet::from(["spanish/index.md > Verbsser".to_string()]),
+                ),
+                (
+                    "house/index.md".to_string(),
+                    HashSet::from(["house/index.mdcasa".to_string()]),
+                ),
+            ],
+        );
+        files.insert(
+            "notes.md".to_string(),
+            vec![("a/b/notes.md".to_string(), HashSet::new())],
+        );
+        files.insert(
+            "root.md".to_string(),
+            vec![("root.md".to_string(), HashSet::new())],
+        );
+
+        let ambiguous = relabel_prefixes(&mut value, &files);
+        assert_eq!(ambiguous, vec!["index.mdunknown"]);
+        let prefixes: Vec<&str> = value["cards"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|x| x["content"]["prefix"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            prefixes,
+            vec![
+                "spanish/index.md > Verbs",
+                "house/index.md",
+                "index.md",
+                "a/b/notes.md"
+            ]
+        );
+        assert_eq!(value["orphans"][0]["content"]["prefix"], "root.md");
+    }
+}
@@ -0,0 +1,305 @@
+use crate::card::{Card, CardCollection};
+use crate::cardcache::get_md_files_in_path;
+use crate::date::Date;
+use crate::deck::Deck;
+use crate::fsrs::{FSRSParams, FSRSState};
+use crate::parsing;
+use std::collections::HashMap;
+use std::error::Error;
+use std::ffi::OsStr;
+use std::{env, fs};
+
+const SR_COMMENT_START: &str = "<!--SR:";
+const SR_COMMENT_END: &str = "-->";
+
+/// Review schedule stored by the Obsidian Spaced Repetition plugin
+#[derive(Debug, PartialEq)]
+pub struct SrSchedule {
+    pub due: Date,
+    pub interval: u32,
+    /// Ease in percent, 250 is the default
+    pub ease: u32,
+}
+
+/// Parses the schedules of a comment such as <!--SR:!2024-03-01,12,250-->, cloze and reversed cards have one for each card
+pub fn parse_sr_comment(line: &str) -> Option<Vec<SrSchedule>> {
+    let start = line.find(SR_COMMENT_START)? + SR_COMMENT_START.len();
+    let end = line[start..].find(SR_COMMENT_END)? + start;
+
+    line[start..end]
+        .split('!')
+        .filter(|x| !x.trim().is_empty())
+        .map(|schedule| {
+            let mut parts = schedule.split(',').map(|x| x.trim());
+            Some(SrSchedule {
+                due: Date::parse(parts.next()?)?,
+                interval: parts.next()?.parse().ok()?,
+                ease: parts.next()?.parse().ok()?,
+            })
+        })
+        .collect()
+}
+
+/// Splits the comment of the plugin from the end of a card line, it isn't part of the card
+pub fn split_sr_comment(line: &str) -> &str {
+    let trimmed = line.trim_end();
+    match trimmed.rfind(SR_COMMENT_START) {
+        Some(start) if trimmed.ends_with(SR_COMMENT_END) => trimmed[..start].trim_end(),
+        _ => line,
+    }
+}
+
+/// Removes the comments, lines with only a comment are removed entirely
+pub fn strip_sr_comments(contents: &str) -> String {
+    let mut output = String::with_capacity(contents.len());
+    for line in contents.split_inclusive('\n') {
+        let start = match line.find(SR_COMMENT_START) {
+            Some(start) => start,
+            None => {
+                output.push_str(line);
+                continue;
+            }
+        };
+        let end = match line[start..].find(SR_COMMENT_END) {
+            Some(end) => start + end + SR_COMMENT_END.len(),
+            None => {
+                output.push_str(line);
+                continue;
+            }
+        };
+
+        let before = line[..start].trim_end();
+        if before.is_empty() && line[end..].trim().is_empty() {
+            continue;
+        }
+        output.push_str(before);
+        output.push_str(&line[end..]);
+    }
+    output
+}
+
+/// Finds the cards of the file that have a comment on their last line or on its own line right after the card
+pub fn find_sr_cards(contents: &str, heading: &str, date: Date) -> Vec<(Card, Vec<SrSchedule>)> {
+    let mut cards: Vec<Option<Card>> = vec![];
+    let mut last_lines: HashMap<usize, usize> = HashMap::new();
+    for (card, lines) in parsing::parse_cards_with_lines(contents, date, Some(heading.to_string()))
+    {
+        last_lines.insert(lines.end - 1, cards.len());
+        cards.push(Some(card));
+    }
+
+    let mut result = vec![];
+    for (index, line) in contents.lines().enumerate() {
+        let schedules = match parse_sr_comment(line) {
+            Some(schedules) => schedules,
+            None => continue,
+        };
+        // Comments of cards that only the plugin reads must not be given to the card before them
+        let own_line = line.trim_start().starts_with(SR_COMMENT_START);
+        let card_index = match last_lines.get(&index) {
+            Some(card_index) => Some(card_index),
+            None if own_line && index > 0 => last_lines.get(&(index - 1)),
+            None => None,
+        };
+        if let Some(card) = card_index.and_then(|x| cards[*x].take()) {
+            result.push((card, schedules));
+        }
+    }
+
+    result
+}
+
+/// Memory state matching the interval and ease of the plugin
+pub fn state_from_sr(schedule: &SrSchedule, params: &FSRSParams) -> FSRSState {
+    let last_review = schedule
+        .due
+        .checked_add_days(-(schedule.interval as i32))
+        .unwrap();
+    let mut state = FSRSState::new(last_review);
+    state.complete_history = false;
+    state.stability = params.stability_for_interval(schedule.interval.max(1) as f64);
+    // Ease goes from 130 for the hardest cards to about 300 for easy ones
+    state.difficulty = (11.0 - schedule.ease as f64 / 50.0).clamp(1.0, 10.0);
+    state.review_date = schedule.due;
+    state
+}
+
+/// Initializes the new cards of the deck from the comments, returns the number of cards initialized
+pub fn apply_sr_schedules(
+    deck: &mut Deck,
+    found: Vec<(Card, Vec<SrSchedule>)>,
+) -> Result<usize, Box<dyn Error>> {
+    let mut states: HashMap<String, FSRSState> = HashMap::new();
+    for (card, schedules) in found {
+        // Cloze cards get the schedules in order
+        let collection = CardCollection::from(vec![card])?;
+        for (card, schedule) in collection.cards.iter().zip(schedules.iter()) {
+            let params = deck.params_for(card);
+            states.insert(card.content.key(), state_from_sr(schedule, params));
+        }
+    }
+
+    let mut count = 0;
+    for card in deck.cards.iter_mut() {
+        if !card.fsrs_state.is_new() {
+            continue;
+        }
+        if let Some(state) = states.remove(&card.content.key()) {
+            card.fsrs_state = state;
+            count += 1;
+        }
+    }
+    Ok(count)
+}
+
+/// Imports the comments of all the markdown files in the working directory
+pub fn import_sr(deck: &mut Deck) -> Result<usize, Box<dyn Error>> {
+    let date = Date::now();
+    let current_dir = env::current_dir()?;
+
+    let mut found = vec![];
+    for file in get_md_files_in_path(OsStr::new(&current_dir)) {
+        let contents = parsing::read_to_string(&file.path);
+        found.extend(find_sr_cards(&contents, &file.relative_path, date));
+    }
+    apply_sr_schedules(deck, found)
+}
+
+/// Removes the comments from the markdown files in the working directory
+pub fn strip_sr_files() -> Result<(), Box<dyn Error>> {
+    let current_dir = env::current_dir()?;
+    for file in get_md_files_in_path(OsStr::new(&current_dir)) {
+        let contents = parsing::read_to_string(&file.path);
+        let stripped = strip_sr_comments(&contents);
+        if stripped != contents {
+            fs::write(&file.path, stripped)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::card::split_markers;
+
+    fn date(year: i32, month: u32, day: u32) -> Date {
+        Date::from_ymd_opt(year, month, day).unwrap()
+    }
+
+    const NOTE: &str = "# Capitals
+Spain:: Madrid <!--SR:!2024-03-01,12,250-->
+:::
+France
+:::
+Paris
+:::
+<!--SR:!2024-03-05,3,130-->
+:: {{{Rome}}} is in {{{Italy}}} <!--SR:!2024-03-02,4,250!2024-03-03,5,270-->
+";
+
+    #[test]
+    fn comments_are_parsed() {
+        assert_eq!(
+            parse_sr_comment("a::b <!--SR:!2024-03-01,12,250!2024-03-05,3,230-->"),
+            Some(vec![
+                SrSchedule {
+                    due: date(2024, 3, 1),
+                    interval: 12,
+                    ease: 250
+                },
+                SrSchedule {
+                    due: date(2024, 3, 5),
+                    interval: 3,
+                    ease: 230
+                }
+            ])
+        );
+        assert_eq!(parse_sr_comment("a::b <!--SR:!2024-03-01,x,250-->"), None);
+        assert_eq!(parse_sr_comment("a::b"), None);
+    }
+
+    #[test]
+    fn comments_are_stripped() {
+        assert_eq!(
+            strip_sr_comments(NOTE),
+            "# Capitals\nSpain:: Madrid\n:::\nFrance\n:::\nParis\n:::\n:: {{{Rome}}} is in {{{Italy}}}\n"
+        );
+    }
+
+    #[test]
+    fn comments_are_split_from_card_lines() {
+        let (text, _, id) = split_markers("a:: b <!--SR:!2024-03-01,12,250--> ^tm-abc");
+        assert_eq!((text, id.unwrap().value()), ("a:: b", "tm-abc"));
+        let (text, _, _) = split_markers("a:: b ^tm-abc <!--SR:!2024-03-01,12,250-->");
+        assert_eq!(text, "a:: b");
+    }
+
+    #[test]
+    fn comments_of_other_cards_are_ignored() {
+        let note = "a:: b\nc::d <!--SR:!2024-03-01,12,250-->\ne:: f\nQuestion\n?\nAnswer\n<!--SR:!2024-03-02,4,250-->\n";
+        let found = find_sr_cards(note, "note.md", date(2024, 3, 1));
+        assert!(found.is_empty());
+
+        let note = "a:: b\n\n<!--SR:!2024-03-01,12,250-->\ng:: h <!--SR:!2024-03-02,4,250-->\n";
+        let found = find_sr_cards(note, "note.md", date(2024, 3, 1));
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].0.content.front, "g");
+    }
+
+    #[test]
+    fn schedules_are_applied() {
+        let today = date(2024, 3, 1);
+        let found = find_sr_cards(NOTE, "capitals.md", today);
+        assert_eq!(found.len(), 3);
+        assert_eq!(found[0].0.content.front, "Spain");
+        assert_eq!(found[1].0.content.front, "France\n");
+        assert_eq!(found[2].1.len(), 2);
+
+        let mut deck = Deck::new();
+        let cards = parsing::parse_cards(NOTE, today, Some("capitals.md".to_string()));
+        deck.replace_cards(CardCollection::from(cards).unwrap())
+            .unwrap();
+        assert_eq!(apply_sr_schedules(&mut deck, found).unwrap(), 4);
+
+        let spain = deck
+            .cards
+            .iter()
+            .find(|x| x.content.front == "Spain")
+            .unwrap();
+        assert_eq!(spain.fsrs_state.review_date, date(2024, 3, 1));
+        assert_eq!(spain.fsrs_state.last_review, date(2024, 2, 18));
+        assert_eq!(spain.fsrs_state.interval(&deck.params).round(), 12.0);
+        let france = deck
+            .cards
+            .iter()
+            .find(|x| x.content.front == "France\n")
+            .unwrap();
+        assert!(france.fsrs_state.difficulty > spain.fsrs_state.difficulty);
+        let italy = deck
+            .cards
+            .iter()
+            .find(|x| x.content.back == "Italy")
+            .unwrap();
+        assert_eq!(italy.fsrs_state.review_date, date(2024, 3, 3));
+        // Comments are not part of the cards, so the plugin rewriting them doesn't change the keys
+        assert_eq!(spain.content.back, "Madrid");
+        assert!(!italy.content.front.contains(SR_COMMENT_START));
+
+        let stripped = parsing::parse_cards(
+            &strip_sr_comments(NOTE),
+            today,
+            Some("capitals.md".to_string()),
+        );
+        let stripped = CardCollection::from(stripped).unwrap();
+        for card in stripped.cards {
+            let existing = deck
+                .cards
+                .iter()
+                .find(|x| x.content.key() == card.content.key())
+                .unwrap();
+            assert!(!existing.fsrs_state.is_new());
+            assert_eq!(existing.content.back, card.content.back);
+        }
+    }
+}
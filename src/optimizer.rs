@@ -0,0 +1,251 @@
+use crate::card::Card;
+use crate::fsrs::{FSRSParams, FSRSState, ReviewAnswer, ReviewLogItem};
+use crate::rand::SplitMix64;
+
+// Allowed ranges for the weights, same as the ones used by the FSRS reference optimizer
+const WEIGHT_BOUNDS: [(f64, f64); 21] = [
+    (0.1, 100.0),
+    (0.1, 100.0),
+    (0.1, 100.0),
+    (0.1, 100.0),
+    (1.0, 10.0),
+    (0.1, 5.0),
+    (0.1, 5.0),
+    (0.0, 0.8),
+    (0.0, 3.0),
+    (0.1, 0.8),
+    (0.01, 2.5),
+    (0.5, 5.0),
+    (0.01, 0.2),
+    (0.01, 0.9),
+    (0.01, 2.0),
+    (0.0, 1.0),
+    (1.0, 6.0),
+    (0.0, 2.0),
+    (0.0, 2.0),
+    (0.01, 0.8),
+    (0.1, 0.8),
+];
+const MIN_REVIEW_COUNT: usize = 64;
+const GRADIENT_STEP: f64 = 1e-5;
+const ADAM_BETA1: f64 = 0.9;
+const ADAM_BETA2: f64 = 0.999;
+const ADAM_EPSILON: f64 = 1e-8;
+
+pub struct OptimizerSettings {
+    pub epochs: usize,
+    pub batch_size: usize,
+    pub learning_rate: f64,
+    pub seed: u64,
+}
+
+impl Default for OptimizerSettings {
+    fn default() -> Self {
+        OptimizerSettings {
+            epochs: 5,
+            batch_size: 512,
+            learning_rate: 0.04,
+            seed: 0,
+        }
+    }
+}
+
+pub struct OptimizerResult {
+    pub params: FSRSParams,
+    pub review_count: usize,
+    pub initial_loss: f64,
+    pub final_loss: f64,
+}
+
+fn collect_histories(cards: &[Card]) -> Vec<&[ReviewLogItem]> {
+    cards
+        .iter()
+        .filter(|card| card.fsrs_state.complete_history && card.fsrs_state.review_log.len() > 1)
+        .map(|card| card.fsrs_state.review_log.as_slice())
+        .collect()
+}
+
+/// Replays a review history and returns the summed log loss of the recall predictions and the number of predictions made
+fn history_loss(history: &[ReviewLogItem], params: &FSRSParams) -> (f64, usize) {
+    let mut state = FSRSState::new(history[0].day);
+    let mut loss = 0.0;
+    let mut count = 0;
+
+    for (i, item) in history.iter().enumerate() {
+        // Only the first answer of the day is predicted, same as in accuracy calculation
+        if i > 0 && history[i - 1].day != item.day {
+            let retention = state.retention(&item.day, params).clamp(1e-4, 1.0 - 1e-4);
+            loss -= if item.answer == ReviewAnswer::Again {
+                (1.0 - retention).ln()
+            } else {
+                retention.ln()
+            };
+            count += 1;
+        }
+        state.review(item.answer.clone(), &item.day, false, 1.0, params);
+    }
+
+    (loss, count)
+}
+
+fn mean_loss(histories: &[&[ReviewLogItem]], params: &FSRSParams) -> f64 {
+    let (loss, count) = histories
+        .iter()
+        .map(|history| history_loss(history, params))
+        .fold((0.0, 0), |a, b| (a.0 + b.0, a.1 + b.1));
+
+    if count == 0 {
+        0.0
+    } else {
+        loss / count as f64
+    }
+}
+
+fn prediction_count(histories: &[&[ReviewLogItem]], params: &FSRSParams) -> usize {
+    histories
+        .iter()
+        .map(|history| history_loss(history, params).1)
+        .sum()
+}
+
+fn gradient(histories: &[&[ReviewLogItem]], params: &FSRSParams) -> Vec<f64> {
+    let mut shifted = params.clone();
+
+    (0..params.w.len())
+        .map(|i| {
+            let original = params.w[i];
+            shifted.w[i] = original + GRADIENT_STEP;
+            let loss_up = mean_loss(histories, &shifted);
+            shifted.w[i] = original - GRADIENT_STEP;
+            let loss_down = mean_loss(histories, &shifted);
+            shifted.w[i] = original;
+            (loss_up - loss_down) / (2.0 * GRADIENT_STEP)
+        })
+        .collect()
+}
+
+fn shuffle<T>(items: &mut [T], rng: &mut SplitMix64) {
+    for i in (1..items.len()).rev() {
+        let j = rng.next_rand() as usize % (i + 1);
+        items.swap(i, j);
+    }
+}
+
+/// Fits the FSRS weights to the review logs of the cards with Adam, starting from the given parameters
+pub fn optimize(
+    cards: &[Card],
+    initial: &FSRSParams,
+    settings: &OptimizerSettings,
+) -> Result<OptimizerResult, Box<dyn std::error::Error>> {
+    let mut histories = collect_histories(cards);
+    let review_count = prediction_count(&histories, initial);
+
+    if review_count < MIN_REVIEW_COUNT {
+        return Err(format!(
+            "Not enough review history to optimize, found {} reviews and at least {} are needed",
+            review_count, MIN_REVIEW_COUNT
+        )
+        .into());
+    }
+
+    let initial_loss = mean_loss(&histories, initial);
+    let mut params = initial.clone();
+    let mut first_moment = vec![0.0; params.w.len()];
+    let mut second_moment = vec![0.0; params.w.len()];
+    let mut step = 0;
+    let mut rng = SplitMix64::from_seed(settings.seed);
+
+    for _epoch in 0..settings.epochs {
+        shuffle(&mut histories, &mut rng);
+
+        for batch in histories.chunks(settings.batch_size.max(1)) {
+            step += 1;
+            let grad = gradient(batch, &params);
+
+            for i in 0..params.w.len() {
+                first_moment[i] = ADAM_BETA1 * first_moment[i] + (1.0 - ADAM_BETA1) * grad[i];
+                second_moment[i] =
+                    ADAM_BETA2 * second_moment[i] + (1.0 - ADAM_BETA2) * grad[i] * grad[i];
+                let corrected_first = first_moment[i] / (1.0 - ADAM_BETA1.powi(step));
+                let corrected_second = second_moment[i] / (1.0 - ADAM_BETA2.powi(step));
+                let (low, high) = WEIGHT_BOUNDS[i];
+                params.w[i] -= settings.learning_rate * corrected_first
+                    / (corrected_second.sqrt() + ADAM_EPSILON);
+                params.w[i] = params.w[i].clamp(low, high);
+            }
+        }
+    }
+
+    let final_loss = mean_loss(&histories, &params);
+    if final_loss > initial_loss {
+        // Never make the scheduling worse than what it was
+        params = initial.clone();
+    }
+
+    Ok(OptimizerResult {
+        params,
+        review_count,
+        initial_loss,
+        final_loss: final_loss.min(initial_loss),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::date::Date;
+
+    // Generates cards whose answers follow the forgetting curve of the given parameters
+    fn simulated_cards(params: &FSRSParams, count: usize) -> Vec<Card> {
+        let mut rng = SplitMix64::from_seed(7);
+        let mut cards = vec![];
+
+        for _ in 0..count {
+            let mut card = Card::new();
+            let mut date = Date { day: 1000 };
+            card.fsrs_state = FSRSState::new(date);
+            card.fsrs_state
+                .review(ReviewAnswer::Good, &date, true, 1.0, params);
+
+            for _ in 0..6 {
+                // Review a bit late so that forgetting actually happens
+                date = card.fsrs_state.review_date.checked_add_days(2).unwrap();
+                let retention = card.fsrs_state.retention(&date, params);
+                let answer = if rng.next_float(0.0, 1.0) < retention {
+                    ReviewAnswer::Good
+                } else {
+                    ReviewAnswer::Again
+                };
+                card.fsrs_state.review(answer, &date, true, 1.0, params);
+            }
+            cards.push(card);
+        }
+
+        cards
+    }
+
+    #[test]
+    fn not_enough_reviews_is_error() {
+        let cards = simulated_cards(&FSRSParams::new(), 2);
+        let result = optimize(&cards, &FSRSParams::new(), &OptimizerSettings::default());
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn optimizing_reduces_loss() {
+        let mut true_params = FSRSParams::new();
+        true_params.w[8] = 1.0;
+        true_params.w[11] = 1.0;
+        let cards = simulated_cards(&true_params, 300);
+        let settings = OptimizerSettings {
+            epochs: 3,
+            batch_size: 100,
+            ..Default::default()
+        };
+        let result = optimize(&cards, &FSRSParams::new(), &settings).unwrap();
+        assert!(result.final_loss < result.initial_loss);
+        for (i, weight) in result.params.w.iter().enumerate() {
+            assert!(*weight >= WEIGHT_BOUNDS[i].0 && *weight <= WEIGHT_BOUNDS[i].1);
+        }
+    }
+}
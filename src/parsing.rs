@@ -1,8 +1,11 @@
-use crate::card::{Card, CardContent};
+use crate::card::{split_markers, Card, CardContent};
 use crate::cardcache::get_md_files_in_path;
 use crate::date::Date;
 use crate::fsrs::FSRSState;
+use crate::tags::{find_tags, merge_tags};
+use pulldown_cmark::{Event, Options, Parser, Tag};
 use std::ffi::{OsStr, OsString};
+use std::ops::Range;
 use std::string::String;
 use std::vec::Vec;
 use std::{env, fs};
@@ -17,6 +20,8 @@ enum MultilineCardState {
 struct Heading {
     pub title: String,
     pub level: u32,
+    /// Tags in the title, inherited by the cards under the heading
+    pub tags: Vec<String>,
 }
 
 // Return None if not a heading,
@@ -24,7 +29,10 @@ fn check_markdown_heading(line: &str) -> Option<Heading> {
     for (i, c) in line.chars().enumerate() {
         if c != '#' && i == 0 {
             return None;
-        } else if c != '#' && c.is_whitespace() {
+        } else if c != '#' && !c.is_whitespace() {
+            // A tag such as #spanish at the start of the line
+            return None;
+        } else if c != '#' {
             // We have seen both hash and now a non-hash character
             // The title level is the number of hashes seen and the title is the remaining string stripped
             let slice = &line[i..];
@@ -33,6 +41,7 @@ fn check_markdown_heading(line: &str) -> Option<Heading> {
                 return None;
             } else {
                 return Some(Heading {
+                    tags: find_tags(&title),
                     title,
                     level: i as u32,
                 });
@@ -53,6 +62,117 @@ fn create_prefix(headings: &Vec<Heading>) -> String {
     })
 }
 
+// Parts of the markdown where cards, headings and tags are not looked for
+pub(crate) struct IgnoredRanges {
+    /// Code blocks, front matter and HTML blocks such as comments
+    blocks: Vec<Range<usize>>,
+    /// Inline code and inline HTML
+    spans: Vec<Range<usize>>,
+}
+
+impl IgnoredRanges {
+    pub(crate) fn new(input: &str) -> IgnoredRanges {
+        let mut blocks = vec![];
+        let mut spans = vec![];
+        let parser = Parser::new_ext(input, Options::ENABLE_YAML_STYLE_METADATA_BLOCKS);
+        for (event, range) in parser.into_offset_iter() {
+            match event {
+                Event::Start(Tag::CodeBlock(_))
+                | Event::Start(Tag::MetadataBlock(_))
+                | Event::Start(Tag::HtmlBlock) => blocks.push(range),
+                Event::Code(_) | Event::InlineHtml(_) => spans.push(range),
+                _ => (),
+            }
+        }
+        IgnoredRanges { blocks, spans }
+    }
+
+    pub(crate) fn contains(&self, index: usize) -> bool {
+        self.blocks
+            .iter()
+            .chain(self.spans.iter())
+            .any(|x| x.contains(&index))
+    }
+
+    // Indented code blocks start after the indentation, so any overlap with the line counts
+    fn in_block(&self, line_start: usize, line: &str) -> bool {
+        let line_end = line_start + line.len();
+        self.blocks
+            .iter()
+            .any(|x| x.start <= line_end && line_start < x.end)
+    }
+
+    /// Position of the first card separator of the line that isn't inside inline code or HTML
+    fn find_separator(&self, text: &str, line_start: usize) -> Option<usize> {
+        text.match_indices(":: ").map(|(i, _)| i).find(|i| {
+            let index = line_start + i;
+            !self.spans.iter().any(|x| x.contains(&index))
+        })
+    }
+}
+
+/// Settings of a file read from the tmemo keys of its front matter
+#[derive(Debug, Default, PartialEq)]
+pub struct FrontMatter {
+    /// Files with `tmemo: false` have no cards
+    pub excluded: bool,
+    pub tags: Vec<String>,
+    pub preset: Option<String>,
+    pub reverse: bool,
+}
+
+fn yaml_tags(value: &serde_yaml::Value) -> Vec<String> {
+    let tags: Vec<String> = match value {
+        serde_yaml::Value::Sequence(items) => items
+            .iter()
+            .filter_map(|x| x.as_str().map(|x| x.to_string()))
+            .collect(),
+        // Obsidian also accepts a comma separated string
+        serde_yaml::Value::String(text) => text.split(',').map(|x| x.to_string()).collect(),
+        _ => vec![],
+    };
+    tags.iter()
+        .map(|x| x.trim().trim_start_matches('#').to_string())
+        .filter(|x| !x.is_empty())
+        .collect()
+}
+
+/// Reads the front matter at the start of the input, invalid YAML is ignored
+pub fn read_front_matter(input: &str) -> FrontMatter {
+    let mut front_matter = FrontMatter::default();
+    let parser = Parser::new_ext(input, Options::ENABLE_YAML_STYLE_METADATA_BLOCKS);
+    let mut text = String::new();
+    for event in parser {
+        match event {
+            Event::Start(Tag::MetadataBlock(_)) => (),
+            Event::Text(value) => text.push_str(&value),
+            _ => break,
+        }
+    }
+
+    let value: serde_yaml::Value = match serde_yaml::from_str(&text) {
+        Ok(value) => value,
+        Err(_) => return front_matter,
+    };
+    front_matter.excluded = value.get("tmemo").and_then(|x| x.as_bool()) == Some(false);
+    // The Obsidian tags of the note tag the cards as well
+    for key in ["tags", "tmemo-tags"] {
+        if let Some(tags) = value.get(key) {
+            merge_tags(&mut front_matter.tags, &yaml_tags(tags));
+        }
+    }
+    front_matter.preset = value
+        .get("tmemo-preset")
+        .and_then(|x| x.as_str())
+        .map(|x| x.to_string());
+    front_matter.reverse = value.get("tmemo-reverse").and_then(|x| x.as_bool()) == Some(true);
+    front_matter
+}
+
+fn line_start(input: &str, line: &str) -> usize {
+    line.as_ptr() as usize - input.as_ptr() as usize
+}
+
 struct CardLocationData {
     index: usize,
     len: usize,
@@ -73,7 +193,7 @@ pub fn replace_card(
     let location = find_card(input, card, heading)?;
 
     let mut output: String = input[0..location.index].to_string();
-    output.push_str(&new_card.content.to_string());
+    output.push_str(&new_card.content.to_markdown());
     output.push_str(&input[location.index + location.len..]);
 
     Some(output)
@@ -87,14 +207,14 @@ pub fn try_replacing_cards(pairs: Vec<(Card, Card)>) {
     for pair in pairs {
         let md_filename = pair.0.content.get_md_filename();
         for entry in &md_files {
-            if entry.string_filename != md_filename {
+            if entry.relative_path != md_filename {
                 continue;
             }
 
             let contents = read_to_string(&entry.path);
             let replaced = replace_card(
                 &contents,
-                Some(entry.string_filename.clone()),
+                Some(entry.relative_path.clone()),
                 &pair.0,
                 &pair.1,
             )
@@ -107,41 +227,65 @@ pub fn try_replacing_cards(pairs: Vec<(Card, Card)>) {
     }
 }
 
+// Length of the schedule comment and id line following a multiline card, including the line break
+fn marker_line_len(rest: &str) -> usize {
+    let next_line = match rest.strip_prefix('\n') {
+        Some(next) => next.lines().next().unwrap_or(""),
+        None => return 0,
+    };
+    match split_markers(next_line) {
+        ("", schedules, id) if schedules.is_some() || id.is_some() => next_line.len() + 1,
+        _ => 0,
+    }
+}
+
 fn find_card(input: &str, card: &Card, heading: Option<String>) -> Option<CardLocationData> {
     let mut multiline_state = MultilineCardState::None;
     let mut multiline_front = String::new();
     let mut multiline_back = String::new();
     let mut multiline_start: usize = 0;
-    let mut current_line_index: isize;
+    let mut current_line_index: usize;
 
     let mut headings: Vec<Heading> = match heading {
         Some(value) => vec![Heading {
             title: value.to_owned(),
             level: 0,
+            tags: vec![],
         }],
         None => vec![Heading {
             title: "File".to_owned(),
             level: 0,
+            tags: vec![],
         }],
     };
 
+    let ignored = IgnoredRanges::new(input);
     for line in input.lines() {
-        unsafe {
-            current_line_index = line.as_ptr().offset_from(input.as_ptr());
-        }
-        match line.find(":: ") {
+        current_line_index = line_start(input, line);
+        let in_block = ignored.in_block(current_line_index, line);
+        let (text, _, _) = split_markers(line);
+        let separator = match in_block {
+            true => None,
+            false => ignored.find_separator(text, current_line_index),
+        };
+        match separator {
             Some(index) => {
                 let content = CardContent {
                     prefix: create_prefix(&headings),
-                    front: line[0..index].to_string(),
-                    back: line[index + 3..].to_string(),
+                    front: text[0..index].to_string(),
+                    back: text[index + 3..].to_string(),
                     editable: true,
                     base: None,
                     cloze_index: None,
+                    inline_schedules: vec![],
+                    id: None,
+                    tags: vec![],
+                    preset: None,
+                    reverse: false,
                 };
                 if content == card.content && content.back == card.content.back {
                     return Some(CardLocationData {
-                        index: current_line_index as usize,
+                        index: current_line_index,
                         len: line.len(),
                     });
                 }
@@ -149,7 +293,11 @@ fn find_card(input: &str, card: &Card, heading: Option<String>) -> Option<CardLo
             None => (),
         }
 
-        match check_markdown_heading(line) {
+        let heading = match in_block {
+            true => None,
+            false => check_markdown_heading(line),
+        };
+        match heading {
             Some(value) => {
                 let mut insert_index = 1;
                 while insert_index < headings.len() {
@@ -166,10 +314,10 @@ fn find_card(input: &str, card: &Card, heading: Option<String>) -> Option<CardLo
             None => {}
         };
 
-        if line == ":::" {
+        if line == ":::" && !in_block {
             match multiline_state {
                 MultilineCardState::None => {
-                    multiline_start = current_line_index as usize;
+                    multiline_start = current_line_index;
                     multiline_state = MultilineCardState::Front
                 }
                 MultilineCardState::Front => multiline_state = MultilineCardState::Back,
@@ -181,12 +329,18 @@ fn find_card(input: &str, card: &Card, heading: Option<String>) -> Option<CardLo
                         editable: true,
                         base: None,
                         cloze_index: None,
+                        inline_schedules: vec![],
+                        id: None,
+                        tags: vec![],
+                        preset: None,
+                        reverse: false,
                     };
 
                     if content == card.content && content.back == card.content.back {
+                        let end = current_line_index + line.len();
                         return Some(CardLocationData {
                             index: multiline_start,
-                            len: (current_line_index as usize + line.len() - multiline_start),
+                            len: end + marker_line_len(&input[end..]) - multiline_start,
                         });
                     }
 
@@ -298,21 +452,33 @@ impl<'a> Iterator for ClozeIterator<'a> {
 }
 
 fn create_cards(
-    prefix: String,
+    headings: &Vec<Heading>,
     front: String,
     back: String,
     date: Date,
     out_cards: &mut Vec<Card>,
 ) {
+    let mut tags: Vec<String> = vec![];
+    for heading in headings {
+        merge_tags(&mut tags, &heading.tags);
+    }
+    merge_tags(&mut tags, &find_tags(&front));
+    merge_tags(&mut tags, &find_tags(&back));
+
     let card = Card {
         fsrs_state: FSRSState::new(date),
         content: CardContent {
-            prefix: prefix.to_string(),
+            prefix: create_prefix(headings),
             front: front.to_string(),
             back: back.to_string(),
             editable: true,
             base: None,
             cloze_index: None,
+            inline_schedules: vec![],
+            id: None,
+            tags,
+            preset: None,
+            reverse: false,
         },
     };
 
@@ -320,8 +486,26 @@ fn create_cards(
 }
 
 pub fn parse_cards(input: &str, date: Date, heading: Option<String>) -> Vec<Card> {
+    parse_cards_with_lines(input, date, heading)
+        .into_iter()
+        .map(|(card, _)| card)
+        .collect()
+}
+
+/// Parses the cards with the range of lines each card spans
+pub fn parse_cards_with_lines(
+    input: &str,
+    date: Date,
+    heading: Option<String>,
+) -> Vec<(Card, Range<usize>)> {
     let mut vec: Vec<Card> = vec![];
+    let mut lines: Vec<Range<usize>> = vec![];
+    let front_matter = read_front_matter(input);
+    if front_matter.excluded {
+        return vec![];
+    }
     let mut multiline_state = MultilineCardState::None;
+    let mut multiline_start = 0;
     let mut multiline_front = String::new();
     let mut multiline_back = String::new();
 
@@ -329,25 +513,55 @@ pub fn parse_cards(input: &str, date: Date, heading: Option<String>) -> Vec<Card
         Some(value) => vec![Heading {
             title: value.to_owned(),
             level: 0,
+            tags: vec![],
         }],
         None => vec![Heading {
             title: "File".to_owned(),
             level: 0,
+            tags: vec![],
         }],
     };
 
-    for line in input.lines() {
-        if let Some(index) = line.find(":: ") {
+    // Multiline card that a schedule comment on the next line belongs to
+    let mut last_multiline: Option<usize> = None;
+
+    let ignored = IgnoredRanges::new(input);
+    for (line_index, line) in input.lines().enumerate() {
+        let start = line_start(input, line);
+        let in_block = ignored.in_block(start, line);
+        let (text, schedules, id) = split_markers(line);
+        let multiline = last_multiline.take();
+        if text.is_empty() && (schedules.is_some() || id.is_some()) {
+            if let Some(index) = multiline {
+                vec[index].content.inline_schedules = schedules.unwrap_or_default();
+                vec[index].content.id = id;
+            }
+            continue;
+        }
+
+        let separator = match in_block {
+            true => None,
+            false => ignored.find_separator(text, start),
+        };
+        if let Some(index) = separator {
             create_cards(
-                create_prefix(&headings),
-                line[0..index].to_string(),
-                line[index + 3..].to_string(),
+                &headings,
+                text[0..index].to_string(),
+                text[index + 3..].to_string(),
                 date,
                 &mut vec,
             );
+            lines.push(line_index..line_index + 1);
+            let content = &mut vec.last_mut().unwrap().content;
+            content.inline_schedules = schedules.unwrap_or_default();
+            content.id = id;
         }
 
-        if let Some(value) = check_markdown_heading(line) {
+        let heading = match in_block {
+            true => None,
+            false => check_markdown_heading(line),
+        };
+        if let Some(value) = heading {
             let mut insert_index = 1;
             while insert_index < headings.len() {
                 if headings[insert_index].level < value.level {
@@ -361,18 +575,23 @@ pub fn parse_cards(input: &str, date: Date, heading: Option<String>) -> Vec<Card
             headings.truncate(insert_index + 1);
         };
 
-        if line == ":::" {
+        if line == ":::" && !in_block {
             match multiline_state {
-                MultilineCardState::None => multiline_state = MultilineCardState::Front,
+                MultilineCardState::None => {
+                    multiline_state = MultilineCardState::Front;
+                    multiline_start = line_index;
+                }
                 MultilineCardState::Front => multiline_state = MultilineCardState::Back,
                 MultilineCardState::Back => {
                     create_cards(
-                        create_prefix(&headings),
+                        &headings,
                         multiline_front.to_string(),
                         multiline_back.to_string(),
                         date,
                         &mut vec,
                     );
+                    lines.push(multiline_start..line_index + 1);
+                    last_multiline = Some(vec.len() - 1);
                     multiline_front = String::new();
                     multiline_back = String::new();
                     multiline_state = MultilineCardState::None;
@@ -387,12 +606,20 @@ pub fn parse_cards(input: &str, date: Date, heading: Option<String>) -> Vec<Card
         }
     }
 
-    vec
+    for card in vec.iter_mut() {
+        let mut tags = front_matter.tags.clone();
+        merge_tags(&mut tags, &card.content.tags);
+        card.content.tags = tags;
+        card.content.preset = front_matter.preset.clone();
+        card.content.reverse = front_matter.reverse;
+    }
+    vec.into_iter().zip(lines).collect()
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::card::CardId;
     use crate::date::*;
     use crate::parsing::CardContent;
 
@@ -411,6 +638,11 @@ mod tests {
                 editable: true,
                 base: None,
                 cloze_index: None,
+                inline_schedules: vec![],
+                id: None,
+                tags: vec![],
+                preset: None,
+                reverse: false,
             }
         );
         assert_eq!(
@@ -422,6 +654,11 @@ mod tests {
                 editable: true,
                 base: None,
                 cloze_index: None,
+                inline_schedules: vec![],
+                id: None,
+                tags: vec![],
+                preset: None,
+                reverse: false,
             }
         );
         assert_eq!(cards.len(), 2);
@@ -433,14 +670,17 @@ mod tests {
             Heading {
                 title: "a".to_string(),
                 level: 0,
+                tags: vec![],
             },
             Heading {
                 title: "b".to_string(),
                 level: 1,
+                tags: vec![],
             },
             Heading {
                 title: "c".to_string(),
                 level: 2,
+                tags: vec![],
             },
         ];
         let prefix = create_prefix(&headings);
@@ -464,6 +704,11 @@ mod tests {
                 editable: true,
                 base: None,
                 cloze_index: None,
+                inline_schedules: vec![],
+                id: None,
+                tags: vec![],
+                preset: None,
+                reverse: false,
             }
         );
         assert_eq!(
@@ -475,6 +720,11 @@ mod tests {
                 editable: true,
                 base: None,
                 cloze_index: None,
+                inline_schedules: vec![],
+                id: None,
+                tags: vec![],
+                preset: None,
+                reverse: false,
             }
         );
         assert_eq!(cards.len(), 2);
@@ -503,7 +753,7 @@ mod tests {
         back line1\n\
         back line2\n\
         \n\
-        :::\n
+        :::\n\
         test1 :: test2\n";
 
         let cards = parse_cards(input, Date::from_ymd_opt(2024, 1, 1).unwrap(), None);
@@ -535,6 +785,163 @@ mod tests {
         assert_eq!(&replaced.unwrap(), "\r\n best1:: best2\r\n");
     }
 
+    #[test]
+    fn inline_schedules_are_parsed() {
+        let input = "a:: b <!--tmemo:2024-03-01,12.0000,5.0000-->\n\
+                     :::\n\
+                     c\n\
+                     :::\n\
+                     d\n\
+                     :::\n\
+                     <!--tmemo:2024-03-02,3.0000,7.0000-->\n\
+                     after\n";
+        let cards = parse_cards(input, Date::from_ymd_opt(2024, 1, 1).unwrap(), None);
+        assert_eq!(cards.len(), 2);
+        assert_eq!(cards[0].content.back, "b");
+        assert_eq!(cards[0].content.inline_schedules[0].stability, 12.0);
+        assert_eq!(cards[1].content.back, "d\n");
+        assert_eq!(cards[1].content.inline_schedules[0].difficulty, 7.0);
+
+        let mut new_card = cards[1].clone();
+        new_card.content.inline_schedules[0].stability = 4.0;
+        let replaced = replace_card(input, None, &cards[1], &new_card).unwrap();
+        assert!(replaced.ends_with(":::\n<!--tmemo:2024-03-02,4.0000,7.0000-->\nafter\n"));
+        assert!(!replaced.contains("3.0000"));
+    }
+
+    #[test]
+    fn card_ids_are_parsed() {
+        let input = "a:: b ^tm-abc123\n\
+                     c:: d <!--tmemo:2024-03-01,12.0000,5.0000--> <!--tmemo-id:tm-def456-->\n\
+                     e:: f^tm-notanid\n\
+                     :::\n\
+                     g\n\
+                     :::\n\
+                     h\n\
+                     :::\n\
+                     ^tm-ghi789\n";
+        let cards = parse_cards(input, Date::from_ymd_opt(2024, 1, 1).unwrap(), None);
+        assert_eq!(cards.len(), 4);
+        assert_eq!(cards[0].content.back, "b");
+        assert_eq!(
+            cards[0].content.id,
+            Some(CardId::Block("tm-abc123".to_string()))
+        );
+        assert_eq!(cards[1].content.back, "d");
+        assert_eq!(cards[1].content.inline_schedules.len(), 1);
+        assert_eq!(
+            cards[1].content.id,
+            Some(CardId::Comment("tm-def456".to_string()))
+        );
+        assert_eq!(cards[2].content.back, "f^tm-notanid");
+        assert_eq!(cards[2].content.id, None);
+        assert_eq!(
+            cards[3].content.id,
+            Some(CardId::Block("tm-ghi789".to_string()))
+        );
+
+        let mut new_card = cards[3].clone();
+        new_card.content.front = "g2\n".to_string();
+        let replaced = replace_card(input, None, &cards[3], &new_card).unwrap();
+        assert!(replaced.ends_with(":::\ng2\n:::\nh\n:::\n^tm-ghi789\n"));
+    }
+
+    #[test]
+    fn code_and_front_matter_are_skipped() {
+        let input = "---\n\
+                     title: a:: b\n\
+                     ---\n\
+                     # Rust\n\
+                     ```rust\n\
+                     # not a heading\n\
+                     impl std::fmt:: Display for X {}\n\
+                     :::\n\
+                     ```\n\
+                     \n    indented:: code\n\
+                     \n\
+                     <!--\n\
+                     commented:: out\n\
+                     -->\n\
+                     Use `std::fmt:: Display`:: to format <!-- a:: b -->\n\
+                     :::\n\
+                     front\n\
+                     :::\n\
+                     ```\n\
+                     # comment\n\
+                     ```\n\
+                     :::\n";
+        let cards = parse_cards(input, Date::from_ymd_opt(2024, 1, 1).unwrap(), None);
+        assert_eq!(cards.len(), 2);
+        assert_eq!(cards[0].content.prefix, "File > Rust");
+        assert_eq!(cards[0].content.front, "Use `std::fmt:: Display`");
+        assert_eq!(cards[0].content.back, "to format <!-- a:: b -->");
+        assert_eq!(cards[1].content.back, "```\n# comment\n```\n");
+
+        let mut new_card = cards[1].clone();
+        new_card.content.front = "front2\n".to_string();
+        let replaced = replace_card(input, None, &cards[1], &new_card).unwrap();
+        assert!(replaced.ends_with("-->\n:::\nfront2\n:::\n```\n# comment\n```\n:::\n"));
+    }
+
+    #[test]
+    fn front_matter_is_read() {
+        let date = Date::from_ymd_opt(2024, 1, 1).unwrap();
+        let input = "---\n\
+                     title: Spanish\n\
+                     tmemo-tags: [languages, '#spanish']\n\
+                     tmemo-preset: languages\n\
+                     tmemo-reverse: true\n\
+                     ---\n\
+                     casa:: house\n";
+        assert_eq!(
+            read_front_matter(input),
+            FrontMatter {
+                excluded: false,
+                tags: vec!["languages".to_string(), "spanish".to_string()],
+                preset: Some("languages".to_string()),
+                reverse: true,
+            }
+        );
+        let cards = parse_cards(input, date, None);
+        assert_eq!(cards.len(), 1);
+        assert_eq!(cards[0].content.tags, vec!["languages", "spanish"]);
+        assert!(cards[0].content.reverse);
+
+        let input = "---\ntmemo-tags: a, b\n---\ncasa:: house\n";
+        assert_eq!(read_front_matter(input).tags, vec!["a", "b"]);
+        let input = "---\ntmemo: false\n---\ncasa:: house\n";
+        assert!(parse_cards(input, date, None).is_empty());
+        assert_eq!(read_front_matter("casa:: house\n"), FrontMatter::default());
+        assert_eq!(read_front_matter("---\n: [\n---\n"), FrontMatter::default());
+    }
+
+    #[test]
+    fn inline_tags_are_parsed() {
+        let input = "---\n\
+                     tmemo-tags: notes\n\
+                     ---\n\
+                     #draft text\n\
+                     # Spanish #languages/spanish\n\
+                     casa:: house #noun\n\
+                     ## Verbs #verb\n\
+                     comer:: to eat #Languages/Spanish\n\
+                     # Other\n\
+                     `#include`:: C\n";
+        let cards = parse_cards(input, Date::from_ymd_opt(2024, 1, 1).unwrap(), None);
+        assert_eq!(cards.len(), 3);
+        assert_eq!(cards[0].content.prefix, "File > Spanish #languages/spanish");
+        assert_eq!(
+            cards[0].content.tags,
+            vec!["notes", "languages/spanish", "noun"]
+        );
+        assert_eq!(
+            cards[1].content.tags,
+            vec!["notes", "languages/spanish", "verb"]
+        );
+        assert_eq!(cards[1].content.back, "to eat #Languages/Spanish");
+        assert_eq!(cards[2].content.tags, vec!["notes"]);
+    }
+
     #[test]
     fn multiline_parsing_works() {
         let input = "askdjasldkjasldkjqweqwee\n\
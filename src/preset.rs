@@ -0,0 +1,99 @@
+use crate::card::CardContent;
+use crate::fsrs::FSRSParams;
+use serde::{Deserialize, Serialize};
+
+/// Named scheduling parameters for the cards matching one of the patterns
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Preset {
+    pub name: String,
+    pub patterns: Vec<String>,
+    pub params: FSRSParams,
+}
+
+fn is_glob(pattern: &str) -> bool {
+    pattern.contains(['*', '?'])
+}
+
+// * matches within a path component, ** also matches across them
+fn glob_matches_chars(pattern: &[char], text: &[char]) -> bool {
+    match pattern.first() {
+        None => text.is_empty(),
+        Some('*') if pattern.get(1) == Some(&'*') => {
+            let rest = &pattern[2..];
+            // a/**/b should also match a/b
+            if rest.first() == Some(&'/') && glob_matches_chars(&rest[1..], text) {
+                return true;
+            }
+            (0..=text.len()).any(|i| glob_matches_chars(rest, &text[i..]))
+        }
+        Some('*') => {
+            for i in 0..=text.len() {
+                if glob_matches_chars(&pattern[1..], &text[i..]) {
+                    return true;
+                }
+                if i < text.len() && text[i] == '/' {
+                    break;
+                }
+            }
+            false
+        }
+        Some('?') => {
+            !text.is_empty() && text[0] != '/' && glob_matches_chars(&pattern[1..], &text[1..])
+        }
+        Some(c) => text.first() == Some(c) && glob_matches_chars(&pattern[1..], &text[1..]),
+    }
+}
+
+pub fn glob_matches(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    glob_matches_chars(&pattern, &text)
+}
+
+impl Preset {
+    /// Glob patterns are matched against the file of the card, other patterns against the start of the prefix
+    pub fn matches(&self, content: &CardContent) -> bool {
+        self.patterns.iter().any(|pattern| {
+            if is_glob(pattern) {
+                glob_matches(pattern, content.get_md_filename())
+                    || glob_matches(pattern, &content.prefix)
+            } else {
+                content.prefix.starts_with(pattern.as_str())
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn glob_matching_works() {
+        assert!(glob_matches("languages/**", "languages/spanish/verbs.md"));
+        assert!(glob_matches("languages/*.md", "languages/verbs.md"));
+        assert!(!glob_matches(
+            "languages/*.md",
+            "languages/spanish/verbs.md"
+        ));
+        assert!(glob_matches("**/verbs.md", "verbs.md"));
+        assert!(glob_matches("trivia?.md", "trivia2.md"));
+        assert!(!glob_matches("trivia.md", "trivia2.md"));
+    }
+
+    #[test]
+    fn preset_matching_works() {
+        let preset = Preset {
+            name: "spanish".to_string(),
+            patterns: vec!["spanish.md > Verbs".to_string(), "*.es.md".to_string()],
+            params: FSRSParams::new(),
+        };
+        let mut content = CardContent::new();
+        content.prefix = "spanish.md > Verbs > Irregular".to_string();
+        assert!(preset.matches(&content));
+        content.prefix = "spanish.md > Nouns".to_string();
+        assert!(!preset.matches(&content));
+        content.prefix = "food.es.md > Fruit".to_string();
+        assert!(preset.matches(&content));
+    }
+}
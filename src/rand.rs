@@ -1,5 +1,15 @@
 use serde::{Deserialize, Serialize};
 
+/// FNV-1a hash of the text, unlike the std hasher it stays the same between versions
+pub fn fnv1a(text: &str) -> u64 {
+    let mut hash: u64 = 0xcbf29ce484222325;
+    for byte in text.bytes() {
+        hash ^= byte as u64;
+        hash = hash.wrapping_mul(0x100000001b3);
+    }
+    hash
+}
+
 #[derive(Clone, Debug, Deserialize, Serialize)]
 pub struct SplitMix64 {
     state: u64,
@@ -0,0 +1,128 @@
+use crate::card::Card;
+use serde::{Deserialize, Serialize};
+
+/// Pairings scoring below this are not proposed
+pub const RELINK_MIN_SCORE: f64 = 0.6;
+
+/// Proposal to give the state of an orphan to a new card
+#[derive(Clone, Debug, Deserialize, PartialEq, Serialize)]
+pub struct Relink {
+    pub orphan: usize,
+    pub card: usize,
+    pub score: f64,
+}
+
+/// Levenshtein distance in characters
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+
+    for (i, a_char) in a.chars().enumerate() {
+        current[0] = i + 1;
+        for j in 0..b.len() {
+            let substitution = previous[j] + (a_char != b[j]) as usize;
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    previous[b.len()]
+}
+
+fn text_similarity(a: &str, b: &str) -> f64 {
+    let (a, b) = (a.trim(), b.trim());
+    let len = a.chars().count().max(b.chars().count());
+    if len == 0 {
+        return 1.0;
+    }
+    1.0 - edit_distance(a, b) as f64 / len as f64
+}
+
+/// Score between 0 and 1 from the similarity of the front and back and whether the cards are in the same file
+pub fn similarity(orphan: &Card, card: &Card) -> f64 {
+    let front = text_similarity(&orphan.content.front, &card.content.front);
+    let back = text_similarity(&orphan.content.back, &card.content.back);
+    let same_file = orphan.content.get_md_filename() == card.content.get_md_filename();
+    0.5 * front + 0.3 * back + if same_file { 0.2 } else { 0.0 }
+}
+
+/// Best pairings of the reviewed orphans with the candidate cards, each orphan and card is used once
+pub fn propose_relinks(cards: &[Card], orphans: &[Card], candidates: &[usize]) -> Vec<Relink> {
+    let mut scored: Vec<Relink> = vec![];
+    for (orphan_index, orphan) in orphans.iter().enumerate() {
+        if orphan.fsrs_state.is_new() {
+            continue;
+        }
+        for card_index in candidates {
+            let score = similarity(orphan, &cards[*card_index]);
+            if score >= RELINK_MIN_SCORE {
+                scored.push(Relink {
+                    orphan: orphan_index,
+                    card: *card_index,
+                    score,
+                });
+            }
+        }
+    }
+    scored.sort_by(|a, b| b.score.total_cmp(&a.score));
+
+    let mut relinks: Vec<Relink> = vec![];
+    for relink in scored {
+        let taken = relinks
+            .iter()
+            .any(|x| x.orphan == relink.orphan || x.card == relink.card);
+        if !taken {
+            relinks.push(relink);
+        }
+    }
+    relinks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::card::Card;
+    use crate::date::Date;
+    use crate::fsrs::FSRSState;
+
+    fn card(prefix: &str, front: &str, back: &str) -> Card {
+        let mut card = Card::new();
+        card.content.prefix = prefix.to_string();
+        card.content.front = front.to_string();
+        card.content.back = back.to_string();
+        card
+    }
+
+    #[test]
+    fn edit_distance_works() {
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("päivä", "paiva"), 2);
+        assert_eq!(edit_distance("same", "same"), 0);
+    }
+
+    #[test]
+    fn relinks_are_proposed() {
+        let mut orphans = vec![
+            card("spanish.md > Verbs", "to eat", "comer"),
+            card("spanish.md > Nouns", "house", "casa"),
+            card("other.md", "something else", "entirely"),
+        ];
+        for orphan in orphans.iter_mut() {
+            orphan.fsrs_state = FSRSState::new(Date { day: 10 });
+            orphan.fsrs_state.stability = 5.0;
+        }
+        let cards = vec![
+            card("spanish.md > Food", "to eat (verb)", "comer"),
+            card("spanish.md > Home", "the house", "casa"),
+            card("spanish.md > Home", "garden", "jardín"),
+        ];
+
+        let relinks = propose_relinks(&cards, &orphans, &[0, 1, 2]);
+        assert_eq!(relinks.len(), 2);
+        assert_eq!((relinks[0].orphan, relinks[0].card), (1, 1));
+        assert_eq!((relinks[1].orphan, relinks[1].card), (0, 0));
+        assert!(relinks[0].score > relinks[1].score);
+        assert!(propose_relinks(&cards, &orphans, &[2]).is_empty());
+    }
+}
@@ -1,6 +1,10 @@
 use crate::card::{Card, CardContent};
+use crate::date::current_timestamp;
+use crate::fsrs::ReviewAnswer;
 use crate::parsing::ClozeIterator;
-use crate::state::EditMode;
+use crate::state::{EditMode, MainMenuItem};
+use crate::stats::{self, DeckStats, STATS_DAYS};
+use crate::tags::strip_tags;
 use crate::{date::Date, state};
 use ratatui::{prelude::*, widgets::*};
 
@@ -52,6 +56,10 @@ pub fn render_app(frame: &mut Frame, state: &state::TMemoInternalState) {
         state::TMemoStateView::Find => render_find(frame, state),
         state::TMemoStateView::Hotkeys => render_hotkeys(frame, state),
         state::TMemoStateView::Edit => render_edit_card(frame, state),
+        state::TMemoStateView::Leeches => render_leeches(frame, state),
+        state::TMemoStateView::Relink => render_relink(frame, state),
+        state::TMemoStateView::Stats => render_stats(frame, state),
+        state::TMemoStateView::Heatmap => render_heatmap(frame, state),
     }
 }
 
@@ -64,12 +72,23 @@ fn render_output(frame: &mut Frame, state: &state::TMemoInternalState, rect: Rec
     frame.render_widget(par.block(block), rect);
 }
 
-fn render_review_finished(frame: &mut Frame, _state: &state::TMemoInternalState) {
+fn render_review_finished(frame: &mut Frame, state: &state::TMemoInternalState) {
     let areas = Layout::new(Direction::Vertical, [Constraint::Min(1)]).split(frame.size());
 
-    let text = Line::from(Span::raw(
-        "Review has been finished! Press Enter or Esc to quit review",
-    ));
+    let text = match state.deck.next_step_due() {
+        Some(due) => {
+            let minutes = ((due - current_timestamp()) as f64 / 60000.0)
+                .ceil()
+                .max(0.0);
+            Line::from(Span::raw(format!(
+                "Next learning step is due in {} minutes. Press Enter to continue or Esc to quit review",
+                minutes
+            )))
+        }
+        None => Line::from(Span::raw(
+            "Review has been finished! Press Enter or Esc to quit review",
+        )),
+    };
 
     let block1 = Block::new()
         .borders(Borders::TOP | Borders::LEFT | Borders::RIGHT | Borders::BOTTOM)
@@ -82,7 +101,7 @@ fn render_review_finished(frame: &mut Frame, _state: &state::TMemoInternalState)
 }
 
 fn get_front_text(content: &CardContent) -> Vec<Line<'_>> {
-    let front_text = format_md_text(&content.front);
+    let front_text = format_md_text(&strip_tags(&content.front));
     let mut output: Vec<Line<'_>> = Vec::new();
     if !front_text.contains("{...}") {
         let front_lines = front_text.lines();
@@ -107,7 +126,7 @@ fn get_front_text(content: &CardContent) -> Vec<Line<'_>> {
 }
 
 fn get_back_text(content: &CardContent) -> Vec<Line<'_>> {
-    let back_text = format_md_text(&content.back);
+    let back_text = format_md_text(&strip_tags(&content.back));
     let mut output: Vec<Line<'_>> = Vec::new();
     if ClozeIterator::new(crate::parsing::ClozeType::TripleBrace, &back_text)
         .next()
@@ -139,6 +158,23 @@ fn get_back_text(content: &CardContent) -> Vec<Line<'_>> {
     output
 }
 
+// Time until the card is shown again, learning steps are in minutes and other intervals in days
+fn interval_text(state: &state::TMemoInternalState, card: &Card, answer: ReviewAnswer) -> String {
+    if let Some((_, minutes)) = state
+        .deck
+        .next_learning_step(&card.fsrs_state, answer.clone())
+    {
+        return format!("{} min", minutes);
+    }
+    let days = card.fsrs_state.next_interval(
+        answer,
+        &state.deck.review_date.unwrap(),
+        &state.rng,
+        state.deck.params_for(card),
+    );
+    format!("{} days", days)
+}
+
 fn render_review_in_progress(frame: &mut Frame, state: &state::TMemoInternalState, card: &Card) {
     let areas = Layout::new(
         Direction::Vertical,
@@ -152,34 +188,22 @@ fn render_review_in_progress(frame: &mut Frame, state: &state::TMemoInternalStat
     .split(frame.size());
 
     let text1 = get_front_text(&card.content);
-    let keys = Line::from(Span::raw("[Enter] Show [Esc] Quit review [B] Bury"));
+    let keys = Line::from(Span::raw(
+        "[Enter] Show [Esc] Quit review [B] Bury until tomorrow [S] Suspend",
+    ));
 
-    let hard_interval = card.fsrs_state.next_interval(
-        crate::fsrs::ReviewAnswer::Hard,
-        &state.deck.review_date.unwrap(),
-        &state.rng,
-        &state.deck.params,
-    );
-    let good_interval = card.fsrs_state.next_interval(
-        crate::fsrs::ReviewAnswer::Good,
-        &state.deck.review_date.unwrap(),
-        &state.rng,
-        &state.deck.params,
-    );
-    let easy_interval = card.fsrs_state.next_interval(
-        crate::fsrs::ReviewAnswer::Easy,
-        &state.deck.review_date.unwrap(),
-        &state.rng,
-        &state.deck.params,
-    );
+    let again_interval = interval_text(state, card, ReviewAnswer::Again);
+    let hard_interval = interval_text(state, card, ReviewAnswer::Hard);
+    let good_interval = interval_text(state, card, ReviewAnswer::Good);
+    let easy_interval = interval_text(state, card, ReviewAnswer::Easy);
     let answer_keys: Line;
     let text3: Vec<Line>;
 
     if state.review_show_back {
         text3 = get_back_text(&card.content);
         answer_keys = Line::from(Span::raw(format!(
-            "[1] Again [2] Hard - {} days [3] Good - {} days [4] Easy - {} days",
-            hard_interval, good_interval, easy_interval
+            "[1] Again - {} [2] Hard - {} [3] Good - {} [4] Easy - {}",
+            again_interval, hard_interval, good_interval, easy_interval
         )));
     } else {
         text3 = vec![Line::from(Span::raw(""))];
@@ -192,7 +216,7 @@ fn render_review_in_progress(frame: &mut Frame, state: &state::TMemoInternalStat
         .title(format!(
             "Front ({}) - {} ",
             state.deck.active_review_count(),
-            card.content.prefix
+            strip_tags(&card.content.prefix)
         ));
     let hotkeys_block = Block::new()
         .borders(Borders::LEFT | Borders::RIGHT | Borders::BOTTOM)
@@ -327,15 +351,36 @@ fn render_main(frame: &mut Frame, state: &state::TMemoInternalState) {
     )
     .split(frame.size());
 
-    let rows = vec![
-        format!(
-            "Review ({} cards)",
-            state.deck.cards_to_review_count(Date::now())
-        ),
-        "Review all cards".to_owned(),
-        "Explore cards".to_owned(),
-        "Hotkeys".to_owned(),
-    ];
+    let rows: Vec<String> = state::MAIN_MENU
+        .iter()
+        .map(|item| match item {
+            MainMenuItem::Review => {
+                let (new, reviews) = state.deck.due_counts(Date::now());
+                format!("Review ({} new, {} reviews)", new, reviews)
+            }
+            MainMenuItem::ReviewAll => "Review all cards".to_owned(),
+            MainMenuItem::ReviewOrder => format!("Order: {}", state.deck.review_order.name()),
+            MainMenuItem::NewCardPosition => {
+                format!("New cards: {}", state.deck.new_card_position.name())
+            }
+            MainMenuItem::ReviewTag => match &state.deck.review_tag {
+                Some(tag) => format!("Tag: #{}", tag),
+                None => "Tag: all cards".to_owned(),
+            },
+            MainMenuItem::Explore => "Explore cards".to_owned(),
+            MainMenuItem::Leeches => format!("Leeches ({})", state.deck.leech_indices().len()),
+            MainMenuItem::Relink => {
+                format!("Relink orphans ({})", state.deck.relink_proposals.len())
+            }
+            MainMenuItem::Stats => "Statistics".to_owned(),
+            MainMenuItem::Heatmap => {
+                let reviews = stats::reviews_by_day(&state.deck);
+                let streaks = stats::streaks(&reviews, Date::now());
+                format!("Heatmap ({} day streak)", streaks.current)
+            }
+            MainMenuItem::Hotkeys => "Hotkeys".to_owned(),
+        })
+        .collect();
 
     let text: Vec<Line> = rows
         .iter()
@@ -406,10 +451,18 @@ fn render_find(frame: &mut Frame, state: &state::TMemoInternalState) {
             } else {
                 character = ' ';
             }
+            let card = &state.deck.cards[*card_index];
+            // Mark the cards that are left out of reviews
+            let hidden = if card.fsrs_state.is_hidden(&Date::now()) {
+                "[S] "
+            } else {
+                ""
+            };
             format!(
-                "{} {}",
+                "{} {}{}",
                 character,
-                state.deck.cards[*card_index].content.get_singleline_front()
+                hidden,
+                card.content.get_singleline_front()
             )
         })
         .collect();
@@ -435,6 +488,249 @@ fn render_find(frame: &mut Frame, state: &state::TMemoInternalState) {
     frame.render_widget(Paragraph::new(text).block(block), areas[1]);
 }
 
+fn render_leeches(frame: &mut Frame, state: &state::TMemoInternalState) {
+    let areas = Layout::new(
+        Direction::Vertical,
+        [Constraint::Min(1), Constraint::Max(3)],
+    )
+    .split(frame.size());
+
+    let leeches = state.deck.leech_indices();
+    // Keep the selected leech visible
+    let row_count = areas[0].rows().count().saturating_sub(2).max(1);
+    let first_row = (state.leech_index + 1).saturating_sub(row_count);
+
+    let text: Vec<Line> = leeches
+        .iter()
+        .enumerate()
+        .skip(first_row)
+        .take(row_count)
+        .map(|(index, card_index)| {
+            let card = &state.deck.cards[*card_index];
+            let character = if index == state.leech_index { '>' } else { ' ' };
+            Line::from(Span::raw(format!(
+                "{} {} - {} ({} lapses) {}",
+                character,
+                card.content.get_md_filename(),
+                card.content.get_heading(),
+                card.fsrs_state.lapses(),
+                card.content.get_singleline_front()
+            )))
+        })
+        .collect();
+
+    let block = Block::new()
+        .borders(Borders::ALL)
+        .border_type(BorderType::Rounded)
+        .title(format!("Leeches ({})", leeches.len()));
+    frame.render_widget(Paragraph::new(text).block(block), areas[0]);
+
+    let keys = Paragraph::new(Line::from(Span::raw(
+        "[Enter] Edit [C] Clear leech [Esc] Back",
+    )))
+    .block(
+        Block::new()
+            .borders(Borders::ALL)
+            .border_type(BorderType::Rounded),
+    );
+    frame.render_widget(keys, areas[1]);
+}
+
+fn render_relink(frame: &mut Frame, state: &state::TMemoInternalState) {
+    let areas = Layout::new(
+        Direction::Vertical,
+        [Constraint::Min(1), Constraint::Max(3)],
+    )
+    .split(frame.size());
+
+    // Each pairing takes three rows, keep the selected one visible
+    let proposals = &state.deck.relink_proposals;
+    let row_count = (areas[0].rows().count().saturating_sub(2) / 3).max(1);
+    let first_row = (state.relink_index + 1).saturating_sub(row_count);
+
+    let text: Vec<Line> = proposals
+        .iter()
+        .enumerate()
+        .skip(first_row)
+        .flat_map(|(index, relink)| {
+            let orphan = &state.deck.orphans[relink.orphan].content;
+            let card = &state.deck.cards[relink.card].content;
+            let character = if index == state.relink_index {
+                '>'
+            } else {
+                ' '
+            };
+            vec![
+                Line::from(Span::raw(format!(
+                    "{} {:.0}% {} - {}",
+                    character,
+                    relink.score * 100.0,
+                    orphan.prefix,
+                    orphan.get_singleline_front()
+                ))),
+                Line::from(Span::raw(format!(
+                    "    -> {} - {}",
+                    card.prefix,
+                    card.get_singleline_front()
+                ))),
+                Line::from(""),
+            ]
+        })
+        .collect();
+
+    let block = rounded_block(format!("Relink orphans ({})", proposals.len()));
+    frame.render_widget(Paragraph::new(text).block(block), areas[0]);
+
+    let keys = Paragraph::new(Line::from(Span::raw(
+        "[Enter] Relink [N] Reject [Esc] Back",
+    )))
+    .block(
+        Block::new()
+            .borders(Borders::ALL)
+            .border_type(BorderType::Rounded),
+    );
+    frame.render_widget(keys, areas[1]);
+}
+
+fn rounded_block(title: String) -> Block<'static> {
+    Block::new()
+        .borders(Borders::ALL)
+        .border_type(BorderType::Rounded)
+        .title(title)
+}
+
+fn retention_text(retention: Option<f64>) -> String {
+    match retention {
+        Some(retention) => format!("{:.1}%", retention * 100.0),
+        None => "-".to_owned(),
+    }
+}
+
+fn render_stats(frame: &mut Frame, state: &state::TMemoInternalState) {
+    let stats = DeckStats::new(&state.deck, Date::now());
+    let areas = Layout::new(
+        Direction::Vertical,
+        [
+            Constraint::Max(4),
+            Constraint::Percentage(35),
+            Constraint::Percentage(20),
+            Constraint::Min(1),
+            Constraint::Max(3),
+        ],
+    )
+    .split(frame.size());
+
+    let counts = &stats.counts;
+    let text = vec![
+        Line::from(Span::raw(format!(
+            "New {}  Learning {}  Young {}  Mature {}  Suspended {}",
+            counts.new, counts.learning, counts.young, counts.mature, counts.suspended
+        ))),
+        Line::from(Span::raw(format!(
+            "True retention: {} last {} days, {} total",
+            retention_text(stats.month_retention),
+            STATS_DAYS,
+            retention_text(stats.total_retention)
+        ))),
+    ];
+    frame.render_widget(
+        Paragraph::new(text).block(rounded_block("Statistics".to_owned())),
+        areas[0],
+    );
+
+    // Fit the bars of every day in the chart
+    let bar_width = (areas[1].width.saturating_sub(2) / STATS_DAYS as u16)
+        .saturating_sub(1)
+        .max(1);
+    let labels: Vec<String> = (0..STATS_DAYS).map(|day| day.to_string()).collect();
+    let forecast: Vec<(&str, u64)> = labels
+        .iter()
+        .zip(stats.due_forecast.iter())
+        .map(|(label, count)| (label.as_str(), *count))
+        .collect();
+    let chart = BarChart::default()
+        .block(rounded_block("Due cards in the next days".to_owned()))
+        .data(&forecast)
+        .bar_width(bar_width)
+        .bar_gap(1);
+    frame.render_widget(chart, areas[1]);
+
+    let max_reviews = stats.reviews_per_day.iter().max().unwrap_or(&0);
+    let sparkline = Sparkline::default()
+        .block(rounded_block(format!(
+            "Reviews per day in the last {} days (max {}, today {})",
+            STATS_DAYS,
+            max_reviews,
+            stats.reviews_per_day.last().unwrap_or(&0)
+        )))
+        .data(&stats.reviews_per_day);
+    frame.render_widget(sparkline, areas[2]);
+
+    let distributions = Layout::new(
+        Direction::Horizontal,
+        [Constraint::Percentage(50), Constraint::Percentage(50)],
+    )
+    .split(areas[3]);
+    let chart = BarChart::default()
+        .block(rounded_block("Stability".to_owned()))
+        .data(&stats.stability)
+        .bar_width(3)
+        .bar_gap(1);
+    frame.render_widget(chart, distributions[0]);
+    let chart = BarChart::default()
+        .block(rounded_block("Difficulty".to_owned()))
+        .data(&stats.difficulty)
+        .bar_width(3)
+        .bar_gap(1);
+    frame.render_widget(chart, distributions[1]);
+
+    let keys = Paragraph::new(Line::from(Span::raw("[Esc] Back"))).block(
+        Block::new()
+            .borders(Borders::ALL)
+            .border_type(BorderType::Rounded),
+    );
+    frame.render_widget(keys, areas[4]);
+}
+
+fn render_heatmap(frame: &mut Frame, state: &state::TMemoInternalState) {
+    let areas = Layout::new(
+        Direction::Vertical,
+        [Constraint::Min(1), Constraint::Max(3)],
+    )
+    .split(frame.size());
+
+    let date = Date::now();
+    let reviews = stats::reviews_by_day(&state.deck);
+    let streaks = stats::streaks(&reviews, date);
+    // Show as many weeks as fit next to the weekday names, up to a year
+    let weeks = (areas[0].width.saturating_sub(6) as usize).clamp(1, 53);
+
+    let mut text: Vec<Line> = stats::heatmap_lines(&reviews, date, weeks)
+        .into_iter()
+        .map(|line| Line::from(Span::raw(line)))
+        .collect();
+    text.push(Line::from(Span::raw("")));
+    text.push(Line::from(Span::raw(format!(
+        "Current streak {} days, longest streak {} days",
+        streaks.current, streaks.longest
+    ))));
+    text.push(Line::from(Span::raw(format!(
+        "{} reviews today",
+        reviews.get(&date.day).unwrap_or(&0)
+    ))));
+    frame.render_widget(
+        Paragraph::new(text).block(rounded_block("Reviews".to_owned())),
+        areas[0],
+    );
+
+    let keys = Paragraph::new(Line::from(Span::raw("[Esc] Back"))).block(
+        Block::new()
+            .borders(Borders::ALL)
+            .border_type(BorderType::Rounded),
+    );
+    frame.render_widget(keys, areas[1]);
+}
+
 fn render_hotkeys(frame: &mut Frame, _state: &state::TMemoInternalState) {
     let areas = Layout::new(Direction::Vertical, [Constraint::Percentage(100)]).split(frame.size());
 
@@ -445,6 +741,7 @@ fn render_hotkeys(frame: &mut Frame, _state: &state::TMemoInternalState) {
         "Ctrl+y - Redo",
         "Enter/Esc - Exit this screen",
         "Ctrl+c - Quit the application (in any view)",
+        "Ctrl+u - Unsuspend the selected card (in explore view)",
         "Esc - Quit the application (in main view)",
     ];
 
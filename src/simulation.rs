@@ -0,0 +1,490 @@
+use crate::card::Card;
+use crate::date::Date;
+use crate::deck::Deck;
+use crate::fsrs::{FSRSState, ReviewAnswer, ReviewTiming};
+use crate::rand::SplitMix64;
+use serde::Serialize;
+
+// Answers longer than this were most likely interrupted and are left out of the time costs
+const MAX_ANSWER_DURATION: u32 = 10 * 60 * 1000;
+
+// Target retentions from 0.70 to 0.95 that are tried when searching for the optimal one
+fn retention_grid() -> Vec<f64> {
+    (70..=95).map(|x| x as f64 / 100.0).collect()
+}
+
+/// Seconds spent on the first review of a new card and on each answer of a later review
+#[derive(Clone, Debug)]
+pub struct SimulationCosts {
+    pub new_card: f64,
+    pub again: f64,
+    pub hard: f64,
+    pub good: f64,
+    pub easy: f64,
+}
+
+impl Default for SimulationCosts {
+    fn default() -> Self {
+        SimulationCosts {
+            new_card: 30.0,
+            again: 25.0,
+            hard: 15.0,
+            good: 10.0,
+            easy: 8.0,
+        }
+    }
+}
+
+impl SimulationCosts {
+    /// Averages the answer durations in the review logs, falling back to the defaults when there are none
+    pub fn from_cards(cards: &[Card]) -> SimulationCosts {
+        // Index 0 is for new cards, the rest for the answers from Again to Easy
+        let mut totals = [(0.0, 0); 5];
+
+        for card in cards.iter().filter(|card| card.fsrs_state.complete_history) {
+            for (i, item) in card.fsrs_state.review_log.iter().enumerate() {
+                let duration = match item.duration {
+                    Some(duration) if duration < MAX_ANSWER_DURATION => duration,
+                    _ => continue,
+                };
+                let index = if i == 0 {
+                    0
+                } else {
+                    match item.answer {
+                        ReviewAnswer::Again => 1,
+                        ReviewAnswer::Hard => 2,
+                        ReviewAnswer::Good => 3,
+                        ReviewAnswer::Easy => 4,
+                        ReviewAnswer::Bury => continue,
+                    }
+                };
+                totals[index].0 += duration as f64 / 1000.0;
+                totals[index].1 += 1;
+            }
+        }
+
+        let defaults = SimulationCosts::default();
+        let average = |index: usize, default: f64| match totals[index] {
+            (_, 0) => default,
+            (total, count) => total / count as f64,
+        };
+        SimulationCosts {
+            new_card: average(0, defaults.new_card),
+            again: average(1, defaults.again),
+            hard: average(2, defaults.hard),
+            good: average(3, defaults.good),
+            easy: average(4, defaults.easy),
+        }
+    }
+
+    fn answer_cost(&self, answer: &ReviewAnswer) -> f64 {
+        match answer {
+            ReviewAnswer::Again => self.again,
+            ReviewAnswer::Hard => self.hard,
+            ReviewAnswer::Good => self.good,
+            ReviewAnswer::Easy => self.easy,
+            ReviewAnswer::Bury => 0.0,
+        }
+    }
+}
+
+/// Answer probabilities, all answers for the first review of a new card and Hard, Good and Easy for recalled cards
+#[derive(Clone, Debug)]
+pub struct AnswerDistribution {
+    pub first: [f64; 4],
+    pub recalled: [f64; 3],
+}
+
+impl Default for AnswerDistribution {
+    fn default() -> Self {
+        AnswerDistribution {
+            first: [0.24, 0.094, 0.495, 0.171],
+            recalled: [0.224, 0.632, 0.144],
+        }
+    }
+}
+
+impl AnswerDistribution {
+    /// Answer frequencies in the review logs, falling back to the defaults when there are none
+    pub fn from_cards(cards: &[Card]) -> AnswerDistribution {
+        let mut first = [0.0; 4];
+        let mut recalled = [0.0; 3];
+
+        for card in cards.iter().filter(|card| card.fsrs_state.complete_history) {
+            let log = &card.fsrs_state.review_log;
+            for (i, item) in log.iter().enumerate() {
+                let index = match item.answer {
+                    ReviewAnswer::Again => 0,
+                    ReviewAnswer::Hard => 1,
+                    ReviewAnswer::Good => 2,
+                    ReviewAnswer::Easy => 3,
+                    ReviewAnswer::Bury => continue,
+                };
+                if i == 0 {
+                    first[index] += 1.0;
+                } else if index > 0 && log[i - 1].day != item.day {
+                    // Only the first answer of the day is a real review
+                    recalled[index - 1] += 1.0;
+                }
+            }
+        }
+
+        let defaults = AnswerDistribution::default();
+        AnswerDistribution {
+            first: if first.iter().sum::<f64>() > 0.0 {
+                first
+            } else {
+                defaults.first
+            },
+            recalled: if recalled.iter().sum::<f64>() > 0.0 {
+                recalled
+            } else {
+                defaults.recalled
+            },
+        }
+    }
+}
+
+// Picks an index with probability proportional to its weight
+fn sample(weights: &[f64], rng: &mut SplitMix64) -> usize {
+    let mut value = rng.next_float(0.0, weights.iter().sum());
+    for (i, weight) in weights.iter().enumerate() {
+        if value < *weight {
+            return i;
+        }
+        value -= weight;
+    }
+    weights.len() - 1
+}
+
+fn answer_from_index(index: usize) -> ReviewAnswer {
+    match index {
+        0 => ReviewAnswer::Again,
+        1 => ReviewAnswer::Hard,
+        2 => ReviewAnswer::Good,
+        _ => ReviewAnswer::Easy,
+    }
+}
+
+pub struct WorkloadSettings {
+    pub days: usize,
+    /// New cards added to the deck each day
+    pub new_cards_per_day: usize,
+    pub costs: SimulationCosts,
+    pub answers: AnswerDistribution,
+    pub new_card_limit: Option<u32>,
+    pub review_limit: Option<u32>,
+    pub seed: u64,
+}
+
+impl WorkloadSettings {
+    /// Settings with the costs, answers and limits of the deck
+    pub fn from_deck(deck: &Deck, days: usize) -> WorkloadSettings {
+        WorkloadSettings {
+            days,
+            new_cards_per_day: 10,
+            costs: SimulationCosts::from_cards(&deck.cards),
+            answers: AnswerDistribution::from_cards(&deck.cards),
+            new_card_limit: deck.new_cards_per_day,
+            review_limit: deck.reviews_per_day,
+            seed: 0,
+        }
+    }
+}
+
+pub struct WorkloadResult {
+    pub target_retention: f64,
+    /// Total study time in seconds
+    pub time: f64,
+    /// Expected number of cards remembered at the end of the simulation
+    pub remembered: f64,
+}
+
+impl WorkloadResult {
+    pub fn time_per_remembered(&self) -> f64 {
+        if self.remembered > 0.0 {
+            self.time / self.remembered
+        } else {
+            f64::INFINITY
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct DaySummary {
+    pub day: usize,
+    /// Cards due at the start of the day before the limits
+    pub due: usize,
+    /// Answers to cards that had been studied before, the first answers of new cards are in new_cards
+    pub reviews: usize,
+    pub new_cards: usize,
+    pub minutes: f64,
+    /// Average expected retention of the learned cards at the end of the day
+    pub retention: f64,
+    /// Cards that have been reviewed at least once
+    pub learned: usize,
+}
+
+pub struct Simulation {
+    pub days: Vec<DaySummary>,
+    pub result: WorkloadResult,
+}
+
+/// Simulates studying the cards scheduled with the deck params, optionally with another target retention
+pub fn simulate(
+    deck: &Deck,
+    settings: &WorkloadSettings,
+    target_retention: Option<f64>,
+) -> Simulation {
+    let mut deck = deck.clone();
+    // Cards of the presets have their own retention and would only add noise
+    let cards: Vec<Card> = deck
+        .cards
+        .drain(0..)
+        .filter(|card| !deck.presets.iter().any(|x| x.matches(&card.content)))
+        .collect();
+    deck.cards = cards;
+    deck.presets.clear();
+    deck.track_review_history = false;
+    deck.new_cards_per_day = settings.new_card_limit;
+    deck.reviews_per_day = settings.review_limit;
+    if let Some(target_retention) = target_retention {
+        deck.params.target_retention = target_retention;
+    }
+
+    let mut rng = SplitMix64::from_seed(settings.seed);
+    let first_day = Date::now();
+    let mut days = Vec::with_capacity(settings.days);
+    let mut total_time = 0.0;
+
+    for i in 0..settings.days {
+        let review_day = first_day.checked_add_days(i as i32).unwrap();
+        for _ in 0..settings.new_cards_per_day {
+            let mut card = Card::new();
+            card.fsrs_state = FSRSState::new(review_day);
+            deck.cards.push(card);
+        }
+
+        let due = deck
+            .cards
+            .iter()
+            .filter(|card| {
+                review_day.is_after(&card.fsrs_state.review_date)
+                    && !card.fsrs_state.is_hidden(&review_day)
+            })
+            .count();
+        deck.start_review(review_day, &mut rng);
+        let mut now = review_day.noon_timestamp();
+        let mut time = 0.0;
+        let mut reviews = 0;
+        let mut new_cards = 0;
+
+        loop {
+            let card = match deck.get_review_card() {
+                Some(card) => card,
+                None => match deck.next_step_due() {
+                    Some(due) => {
+                        // Wait for the next learning step
+                        now = now.max(due);
+                        deck.refresh_review_index(now, &mut rng);
+                        continue;
+                    }
+                    None => break,
+                },
+            };
+
+            let answer;
+            let cost;
+            if card.fsrs_state.is_new() {
+                answer = answer_from_index(sample(&settings.answers.first, &mut rng));
+                cost = settings.costs.new_card;
+                new_cards += 1;
+            } else {
+                let retention = card.fsrs_state.retention(&review_day, &deck.params);
+                answer = if rng.next_float(0.0, 1.0) < retention {
+                    answer_from_index(sample(&settings.answers.recalled, &mut rng) + 1)
+                } else {
+                    ReviewAnswer::Again
+                };
+                cost = settings.costs.answer_cost(&answer);
+                reviews += 1;
+            }
+
+            time += cost;
+            now += (cost * 1000.0) as i64;
+            let timing = ReviewTiming {
+                timestamp: now,
+                duration: (cost * 1000.0) as u32,
+            };
+            deck.review_card_with_timing(answer, Some(timing), &mut rng);
+        }
+        deck.stop_review();
+
+        let tomorrow = review_day.checked_add_days(1).unwrap();
+        let (learned, remembered) = expected_remembered(&deck, tomorrow);
+        total_time += time;
+        days.push(DaySummary {
+            day: i,
+            due,
+            reviews,
+            new_cards,
+            minutes: time / 60.0,
+            retention: if learned > 0 {
+                remembered / learned as f64
+            } else {
+                0.0
+            },
+            learned,
+        });
+    }
+
+    let last_day = first_day.checked_add_days(settings.days as i32).unwrap();
+    let (_, remembered) = expected_remembered(&deck, last_day);
+
+    Simulation {
+        days,
+        result: WorkloadResult {
+            target_retention: deck.params.target_retention,
+            time: total_time,
+            remembered,
+        },
+    }
+}
+
+// Number of learned cards and the expected number of them remembered on the date
+fn expected_remembered(deck: &Deck, date: Date) -> (usize, f64) {
+    let learned: Vec<&Card> = deck
+        .cards
+        .iter()
+        .filter(|card| !card.fsrs_state.is_new())
+        .collect();
+    let remembered = learned
+        .iter()
+        .map(|card| card.fsrs_state.retention(&date, &deck.params))
+        .sum();
+    (learned.len(), remembered)
+}
+
+pub fn simulate_workload(
+    deck: &Deck,
+    settings: &WorkloadSettings,
+    target_retention: f64,
+) -> WorkloadResult {
+    simulate(deck, settings, Some(target_retention)).result
+}
+
+/// Simulates each retention of the grid in its own thread, the results are in the grid order
+pub fn optimal_retention(deck: &Deck, settings: &WorkloadSettings) -> Vec<WorkloadResult> {
+    std::thread::scope(|scope| {
+        let handles: Vec<_> = retention_grid()
+            .into_iter()
+            .map(|retention| scope.spawn(move || simulate_workload(deck, settings, retention)))
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .collect()
+    })
+}
+
+/// Retention that needs the least study time per remembered card
+pub fn best_retention(results: &[WorkloadResult]) -> Option<&WorkloadResult> {
+    results.iter().min_by(|a, b| {
+        a.time_per_remembered()
+            .partial_cmp(&b.time_per_remembered())
+            .unwrap()
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fsrs::ReviewLogItem;
+
+    fn settings() -> WorkloadSettings {
+        WorkloadSettings {
+            days: 60,
+            new_cards_per_day: 5,
+            costs: SimulationCosts::default(),
+            answers: AnswerDistribution::default(),
+            new_card_limit: None,
+            review_limit: None,
+            seed: 0,
+        }
+    }
+
+    #[test]
+    fn costs_are_read_from_review_logs() {
+        let mut card = Card::new();
+        for (answer, duration) in [
+            (ReviewAnswer::Good, 20000),
+            (ReviewAnswer::Again, 30000),
+            (ReviewAnswer::Good, 6000),
+            (ReviewAnswer::Good, 4000),
+        ] {
+            card.fsrs_state.review_log.push(ReviewLogItem {
+                answer,
+                day: Date { day: 1 },
+                timestamp: None,
+                duration: Some(duration),
+            });
+        }
+        let costs = SimulationCosts::from_cards(&[card]);
+        assert_eq!(costs.new_card, 20.0);
+        assert_eq!(costs.again, 30.0);
+        assert_eq!(costs.good, 5.0);
+        assert_eq!(costs.easy, SimulationCosts::default().easy);
+    }
+
+    #[test]
+    fn limits_are_respected() {
+        let settings = WorkloadSettings {
+            new_cards_per_day: 20,
+            new_card_limit: Some(5),
+            review_limit: Some(10),
+            ..settings()
+        };
+        let simulation = simulate(&Deck::new(), &settings, None);
+        assert_eq!(simulation.days.len(), 60);
+        for day in &simulation.days {
+            assert!(day.new_cards <= 5);
+        }
+        // Cards pile up when the intake is larger than the limit
+        let last = simulation.days.last().unwrap();
+        assert!(last.due > 20 * 59 - 5 * 60);
+        assert_eq!(last.learned, 5 * 60);
+    }
+
+    #[test]
+    fn new_cards_are_not_counted_as_reviews() {
+        let settings = WorkloadSettings {
+            days: 1,
+            answers: AnswerDistribution {
+                first: [0.0, 0.0, 1.0, 0.0],
+                ..AnswerDistribution::default()
+            },
+            ..settings()
+        };
+        let simulation = simulate(&Deck::new(), &settings, None);
+        assert_eq!(simulation.days[0].new_cards, 5);
+        assert_eq!(simulation.days[0].reviews, 0);
+    }
+
+    #[test]
+    fn higher_retention_costs_more_time() {
+        let deck = Deck::new();
+        let low = simulate_workload(&deck, &settings(), 0.75);
+        let high = simulate_workload(&deck, &settings(), 0.95);
+        assert!(high.time > low.time);
+        assert!(high.remembered > low.remembered);
+    }
+
+    #[test]
+    fn optimal_retention_covers_grid() {
+        let deck = Deck::new();
+        let results = optimal_retention(&deck, &settings());
+        assert_eq!(results.len(), retention_grid().len());
+        let best = best_retention(&results).unwrap();
+        assert!(best.target_retention >= 0.7 && best.target_retention <= 0.95);
+    }
+}
@@ -1,4 +1,5 @@
-use crate::fsrs::ReviewAnswer;
+use crate::date::current_timestamp;
+use crate::fsrs::{ReviewAnswer, ReviewTiming};
 use crate::rand::SplitMix64;
 use crate::{
     card::{Card, CardCollection, Editable},
@@ -47,6 +48,12 @@ pub struct TMemoInternalState {
     pub edit_mode: EditMode,
     pub edit_return_view: TMemoStateView,
     pub find_state: FindViewState,
+    #[serde(default)]
+    pub card_shown_time: Option<i64>,
+    #[serde(default)]
+    pub leech_index: usize,
+    #[serde(default)]
+    pub relink_index: usize,
 }
 
 #[derive(Debug, Deserialize, Clone, PartialEq, Serialize)]
@@ -56,8 +63,41 @@ pub enum TMemoStateView {
     Hotkeys,
     Find,
     Edit,
+    Leeches,
+    Relink,
+    Stats,
+    Heatmap,
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub enum MainMenuItem {
+    Review,
+    ReviewAll,
+    ReviewOrder,
+    NewCardPosition,
+    ReviewTag,
+    Explore,
+    Leeches,
+    Relink,
+    Stats,
+    Heatmap,
+    Hotkeys,
+}
+
+pub const MAIN_MENU: [MainMenuItem; 11] = [
+    MainMenuItem::Review,
+    MainMenuItem::ReviewAll,
+    MainMenuItem::ReviewOrder,
+    MainMenuItem::NewCardPosition,
+    MainMenuItem::ReviewTag,
+    MainMenuItem::Explore,
+    MainMenuItem::Leeches,
+    MainMenuItem::Relink,
+    MainMenuItem::Stats,
+    MainMenuItem::Heatmap,
+    MainMenuItem::Hotkeys,
+];
+
 #[derive(Debug, Deserialize, Clone, Serialize)]
 pub enum TmemoStateAction {
     Up,
@@ -76,7 +116,10 @@ pub enum TmemoStateAction {
     ExitReview,
     StartHotkeys,
     ShowBack,
-    CardResponse(ReviewAnswer),
+    CardResponse(ReviewAnswer, Option<ReviewTiming>),
+    RefreshReview(i64),
+    SuspendCard(i64),
+    Unsuspend,
     LoadDeck(Box<Deck>),
     StartEdit(EditMode),
     StartBaseEdit(EditMode),
@@ -89,6 +132,13 @@ pub enum TmemoStateAction {
     LoadApplicationState(String),
     EnterView(TMemoStateView),
     StartFindEdit,
+    StartLeechEdit,
+    ClearLeech,
+    AcceptRelink,
+    RejectRelink,
+    CycleReviewOrder,
+    CycleNewCardPosition,
+    CycleReviewTag,
     ToggleClozeType,
 }
 
@@ -228,6 +278,9 @@ impl TMemoInternalState {
             edit_index: None,
             edit_return_view: TMemoStateView::Review,
             find_state: FindViewState::new(),
+            card_shown_time: None,
+            leech_index: 0,
+            relink_index: 0,
         }
     }
 
@@ -238,6 +291,12 @@ impl TMemoInternalState {
         };
     }
 
+    // Like set_review_card but also starts timing the answer for the new card
+    fn show_next_review_card(&mut self) {
+        self.set_review_card();
+        self.card_shown_time = Some(current_timestamp());
+    }
+
     fn process_main_view(self: &mut TMemoInternalState, action: &TmemoStateAction) -> bool {
         match action {
             TmemoStateAction::LoadDeck(deck) => {
@@ -246,7 +305,7 @@ impl TMemoInternalState {
                     .deck
                     .cards
                     .iter()
-                    .filter(|x| !x.fsrs_state.buried)
+                    .filter(|x| !x.fsrs_state.suspended)
                     .count();
                 self.output_text = format!("Loaded deck with {count} cards");
                 true
@@ -258,7 +317,7 @@ impl TMemoInternalState {
                             .deck
                             .cards
                             .iter()
-                            .filter(|x| !x.fsrs_state.buried)
+                            .filter(|x| !x.fsrs_state.suspended)
                             .count();
                         self.output_text = format!("Loaded deck with {count} cards");
                     }
@@ -272,14 +331,14 @@ impl TMemoInternalState {
                 self.view = TMemoStateView::Review;
                 self.review_show_back = false;
                 self.deck.start_all_review(Date::now(), &mut self.rng);
-                self.set_review_card();
+                self.show_next_review_card();
                 true
             }
             TmemoStateAction::StartReview => {
                 self.view = TMemoStateView::Review;
                 self.review_show_back = false;
                 self.deck.start_review(Date::now(), &mut self.rng);
-                self.set_review_card();
+                self.show_next_review_card();
                 true
             }
             TmemoStateAction::StartRandomReview => {
@@ -287,13 +346,25 @@ impl TMemoInternalState {
                 self.review_show_back = false;
                 self.deck
                     .start_random_review(Date::now(), &mut self.rng, 17);
-                self.set_review_card();
+                self.show_next_review_card();
                 true
             }
             TmemoStateAction::StartHotkeys => {
                 self.view = TMemoStateView::Hotkeys;
                 true
             }
+            TmemoStateAction::CycleReviewOrder => {
+                self.deck.review_order = self.deck.review_order.next();
+                true
+            }
+            TmemoStateAction::CycleNewCardPosition => {
+                self.deck.new_card_position = self.deck.new_card_position.next();
+                true
+            }
+            TmemoStateAction::CycleReviewTag => {
+                self.deck.review_tag = self.deck.next_review_tag();
+                true
+            }
             TmemoStateAction::Up => {
                 if self.main_index == 0 {
                     false
@@ -303,7 +374,7 @@ impl TMemoInternalState {
                 }
             }
             TmemoStateAction::Down => {
-                if self.main_index == 3 {
+                if self.main_index as usize == MAIN_MENU.len() - 1 {
                     false
                 } else {
                     self.main_index += 1;
@@ -363,6 +434,8 @@ impl TMemoInternalState {
                     let index: usize;
                     if self.edit_return_view == TMemoStateView::Review {
                         index = self.deck.review_indices[self.deck.review_index.unwrap()];
+                    } else if self.edit_return_view == TMemoStateView::Leeches {
+                        index = self.deck.leech_indices()[self.leech_index];
                     } else {
                         index = self.find_state.search_results[self.find_state.search_index];
                     }
@@ -495,12 +568,27 @@ impl TMemoInternalState {
                 self.review_show_back = true;
                 true
             }
-            TmemoStateAction::CardResponse(answer) => {
-                self.deck.review_card(answer.clone(), &mut self.rng);
-                self.set_review_card();
+            TmemoStateAction::CardResponse(answer, timing) => {
+                self.deck
+                    .review_card_with_timing(answer.clone(), *timing, &mut self.rng);
+                self.show_next_review_card();
+                self.review_show_back = false;
+                true
+            }
+            TmemoStateAction::SuspendCard(now) => {
+                if self.deck.review_index.is_none() {
+                    return false;
+                }
+                self.deck.suspend_review_card(None, &mut self.rng, *now);
+                self.show_next_review_card();
                 self.review_show_back = false;
                 true
             }
+            TmemoStateAction::RefreshReview(now) => {
+                self.deck.refresh_review_index(*now, &mut self.rng);
+                self.show_next_review_card();
+                true
+            }
             _ => false,
         }
     }
@@ -552,26 +640,115 @@ impl TMemoInternalState {
             }
             TmemoStateAction::StartFindEdit => {
                 let card_index = self.find_state.search_results[self.find_state.search_index];
-                if card_index >= self.deck.cards.len() {
+                self.start_card_edit(card_index, TMemoStateView::Find);
+            }
+            TmemoStateAction::Unsuspend => {
+                if let Some(card_index) = self
+                    .find_state
+                    .search_results
+                    .get(self.find_state.search_index)
+                {
+                    self.deck.cards[*card_index].fsrs_state.unsuspend();
+                    self.output_text = "Card unsuspended".to_owned();
+                    return true;
+                }
+            }
+            _ => (),
+        }
+
+        false
+    }
+
+    fn start_card_edit(&mut self, card_index: usize, return_view: TMemoStateView) -> bool {
+        if card_index >= self.deck.cards.len() {
+            return false;
+        }
+        let mut card: Card = self.deck.cards[card_index].clone();
+        self.edit_index = None;
+        if !card.content.editable && card.content.base.is_some() {
+            let parent_index = card.content.base.unwrap();
+            card = self.deck.base_cards[parent_index].clone();
+        } else if !card.content.editable {
+            return false;
+        }
+        self.edit_mode = EditMode::EditFront;
+        self.view = TMemoStateView::Edit;
+        self.current_card = Some(card);
+        self.edit_return_view = return_view;
+        true
+    }
+
+    fn process_leeches(self: &mut TMemoInternalState, action: &TmemoStateAction) -> bool {
+        let leeches = self.deck.leech_indices();
+        match &action {
+            TmemoStateAction::Up => {
+                if self.leech_index == 0 {
                     return false;
                 }
-                let mut card: Card = self.deck.cards[card_index].clone();
-                self.edit_index = None;
-                if !card.content.editable && card.content.base.is_some() {
-                    let parent_index = card.content.base.unwrap();
-                    card = self.deck.base_cards[parent_index].clone();
-                } else if !card.content.editable {
+                self.leech_index -= 1;
+                true
+            }
+            TmemoStateAction::Down => {
+                if self.leech_index + 1 >= leeches.len() {
                     return false;
                 }
-                self.edit_mode = EditMode::EditFront;
-                self.view = TMemoStateView::Edit;
-                self.current_card = Some(card);
-                self.edit_return_view = TMemoStateView::Find;
+                self.leech_index += 1;
+                true
             }
-            _ => (),
+            TmemoStateAction::StartLeechEdit => match leeches.get(self.leech_index) {
+                Some(card_index) => self.start_card_edit(*card_index, TMemoStateView::Leeches),
+                None => false,
+            },
+            TmemoStateAction::ClearLeech => match leeches.get(self.leech_index) {
+                Some(card_index) => {
+                    self.deck.clear_leech(*card_index);
+                    self.leech_index = self.leech_index.min(leeches.len().saturating_sub(2));
+                    self.output_text = "Leech cleared".to_owned();
+                    true
+                }
+                None => false,
+            },
+            _ => false,
         }
+    }
 
-        return false;
+    fn process_relink(self: &mut TMemoInternalState, action: &TmemoStateAction) -> bool {
+        let count = self.deck.relink_proposals.len();
+        match &action {
+            TmemoStateAction::Up => {
+                if self.relink_index == 0 {
+                    return false;
+                }
+                self.relink_index -= 1;
+                true
+            }
+            TmemoStateAction::Down => {
+                if self.relink_index + 1 >= count {
+                    return false;
+                }
+                self.relink_index += 1;
+                true
+            }
+            TmemoStateAction::AcceptRelink => {
+                if self.relink_index >= count {
+                    return false;
+                }
+                self.deck.accept_relink(self.relink_index);
+                self.relink_index = self.relink_index.min(count.saturating_sub(2));
+                self.output_text = "Orphan relinked".to_owned();
+                true
+            }
+            TmemoStateAction::RejectRelink => {
+                if self.relink_index >= count {
+                    return false;
+                }
+                self.deck.reject_relink(self.relink_index);
+                self.relink_index = self.relink_index.min(count.saturating_sub(2));
+                self.output_text = "Pairing rejected".to_owned();
+                true
+            }
+            _ => false,
+        }
     }
 
     pub fn process(self: &mut TMemoInternalState, action: &TmemoStateAction) -> bool {
@@ -588,6 +765,10 @@ impl TMemoInternalState {
                 if *view == TMemoStateView::Find {
                     self.find_state = FindViewState::new();
                     self.update_search_results();
+                } else if *view == TMemoStateView::Leeches {
+                    self.leech_index = 0;
+                } else if *view == TMemoStateView::Relink {
+                    self.relink_index = 0;
                 }
 
                 self.view = view.clone();
@@ -600,8 +781,11 @@ impl TMemoInternalState {
             TMemoStateView::Main => self.process_main_view(action),
             TMemoStateView::Review => self.process_review(action),
             TMemoStateView::Hotkeys => false,
+            TMemoStateView::Stats | TMemoStateView::Heatmap => false,
             TMemoStateView::Find => self.process_find(action),
             TMemoStateView::Edit => self.process_edit(action),
+            TMemoStateView::Leeches => self.process_leeches(action),
+            TMemoStateView::Relink => self.process_relink(action),
         }
     }
 }
@@ -616,16 +800,29 @@ fn to_main_action(event: KeyEvent, _state: &ApplicationState) -> Option<TmemoSta
         }
         (KeyCode::Esc, KeyModifiers::NONE) => Some(TmemoStateAction::Quit),
         (KeyCode::Enter, KeyModifiers::NONE) => {
-            if _state.current_state.main_index == 0 {
-                Some(TmemoStateAction::StartReview)
-            } else if _state.current_state.main_index == 1 {
-                Some(TmemoStateAction::StartAllReview)
-            } else if _state.current_state.main_index == 2 {
-                Some(TmemoStateAction::EnterView(TMemoStateView::Find))
-            } else if _state.current_state.main_index == 3 {
-                Some(TmemoStateAction::StartHotkeys)
-            } else {
-                None
+            match MAIN_MENU.get(_state.current_state.main_index as usize) {
+                Some(MainMenuItem::Review) => Some(TmemoStateAction::StartReview),
+                Some(MainMenuItem::ReviewAll) => Some(TmemoStateAction::StartAllReview),
+                Some(MainMenuItem::ReviewOrder) => Some(TmemoStateAction::CycleReviewOrder),
+                Some(MainMenuItem::NewCardPosition) => Some(TmemoStateAction::CycleNewCardPosition),
+                Some(MainMenuItem::ReviewTag) => Some(TmemoStateAction::CycleReviewTag),
+                Some(MainMenuItem::Explore) => {
+                    Some(TmemoStateAction::EnterView(TMemoStateView::Find))
+                }
+                Some(MainMenuItem::Leeches) => {
+                    Some(TmemoStateAction::EnterView(TMemoStateView::Leeches))
+                }
+                Some(MainMenuItem::Relink) => {
+                    Some(TmemoStateAction::EnterView(TMemoStateView::Relink))
+                }
+                Some(MainMenuItem::Stats) => {
+                    Some(TmemoStateAction::EnterView(TMemoStateView::Stats))
+                }
+                Some(MainMenuItem::Heatmap) => {
+                    Some(TmemoStateAction::EnterView(TMemoStateView::Heatmap))
+                }
+                Some(MainMenuItem::Hotkeys) => Some(TmemoStateAction::StartHotkeys),
+                None => None,
             }
         }
         _ => None,
@@ -641,6 +838,42 @@ fn to_hotkeys_action(event: KeyEvent, _state: &ApplicationState) -> Option<Tmemo
     }
 }
 
+fn to_leeches_action(event: KeyEvent, _state: &ApplicationState) -> Option<TmemoStateAction> {
+    match (event.code, event.modifiers) {
+        (KeyCode::Esc, KeyModifiers::NONE) => {
+            Some(TmemoStateAction::EnterView(TMemoStateView::Main))
+        }
+        (KeyCode::Char('j'), KeyModifiers::NONE) | (KeyCode::Down, KeyModifiers::NONE) => {
+            Some(TmemoStateAction::Down)
+        }
+        (KeyCode::Char('k'), KeyModifiers::NONE) | (KeyCode::Up, KeyModifiers::NONE) => {
+            Some(TmemoStateAction::Up)
+        }
+        (KeyCode::Enter, KeyModifiers::NONE) => Some(TmemoStateAction::StartLeechEdit),
+        (KeyCode::Char('c'), KeyModifiers::NONE) => Some(TmemoStateAction::ClearLeech),
+        _ => None,
+    }
+}
+
+fn to_relink_action(event: KeyEvent, _state: &ApplicationState) -> Option<TmemoStateAction> {
+    match (event.code, event.modifiers) {
+        (KeyCode::Esc, KeyModifiers::NONE) => {
+            Some(TmemoStateAction::EnterView(TMemoStateView::Main))
+        }
+        (KeyCode::Char('j'), KeyModifiers::NONE) | (KeyCode::Down, KeyModifiers::NONE) => {
+            Some(TmemoStateAction::Down)
+        }
+        (KeyCode::Char('k'), KeyModifiers::NONE) | (KeyCode::Up, KeyModifiers::NONE) => {
+            Some(TmemoStateAction::Up)
+        }
+        (KeyCode::Enter, KeyModifiers::NONE) | (KeyCode::Char('y'), KeyModifiers::NONE) => {
+            Some(TmemoStateAction::AcceptRelink)
+        }
+        (KeyCode::Char('n'), KeyModifiers::NONE) => Some(TmemoStateAction::RejectRelink),
+        _ => None,
+    }
+}
+
 fn to_find_action(event: KeyEvent, _state: &ApplicationState) -> Option<TmemoStateAction> {
     match (event.code, event.modifiers) {
         (KeyCode::Esc, KeyModifiers::NONE) => {
@@ -653,6 +886,7 @@ fn to_find_action(event: KeyEvent, _state: &ApplicationState) -> Option<TmemoSta
             Some(TmemoStateAction::Up)
         }
         (KeyCode::Enter, _) => Some(TmemoStateAction::StartFindEdit),
+        (KeyCode::Char('u'), KeyModifiers::CONTROL) => Some(TmemoStateAction::Unsuspend),
         (KeyCode::Char(c), modifiers) => Some(TmemoStateAction::RawKey(c, modifiers)),
         (KeyCode::Backspace, _) => Some(TmemoStateAction::RawBackspace),
         _ => None,
@@ -666,20 +900,22 @@ fn to_review_action(event: KeyEvent, state: &ApplicationState) -> Option<TmemoSt
 
     // Answers are only valid if the back of the card is shown
     if state.current_state.review_show_back {
-        match (event.code, event.modifiers) {
-            (KeyCode::Char('1'), KeyModifiers::NONE) => {
-                return Some(TmemoStateAction::CardResponse(ReviewAnswer::Again))
-            }
-            (KeyCode::Char('2'), KeyModifiers::NONE) => {
-                return Some(TmemoStateAction::CardResponse(ReviewAnswer::Hard))
-            }
-            (KeyCode::Char('3'), KeyModifiers::NONE) => {
-                return Some(TmemoStateAction::CardResponse(ReviewAnswer::Good))
-            }
-            (KeyCode::Char('4'), KeyModifiers::NONE) => {
-                return Some(TmemoStateAction::CardResponse(ReviewAnswer::Easy))
-            }
-            _ => (),
+        let answer = match (event.code, event.modifiers) {
+            (KeyCode::Char('1'), KeyModifiers::NONE) => Some(ReviewAnswer::Again),
+            (KeyCode::Char('2'), KeyModifiers::NONE) => Some(ReviewAnswer::Hard),
+            (KeyCode::Char('3'), KeyModifiers::NONE) => Some(ReviewAnswer::Good),
+            (KeyCode::Char('4'), KeyModifiers::NONE) => Some(ReviewAnswer::Easy),
+            _ => None,
+        };
+
+        if let Some(answer) = answer {
+            let timestamp = current_timestamp();
+            let shown_time = state.current_state.card_shown_time.unwrap_or(timestamp);
+            let timing = ReviewTiming {
+                timestamp,
+                duration: (timestamp - shown_time).clamp(0, u32::MAX as i64) as u32,
+            };
+            return Some(TmemoStateAction::CardResponse(answer, Some(timing)));
         }
     }
 
@@ -703,7 +939,14 @@ fn to_review_action(event: KeyEvent, state: &ApplicationState) -> Option<TmemoSt
         (KeyCode::Esc, KeyModifiers::NONE) => Some(TmemoStateAction::ExitReview),
         (KeyCode::Char('b'), KeyModifiers::NONE) => {
             if state.current_state.deck.review_index.is_some() {
-                Some(TmemoStateAction::CardResponse(ReviewAnswer::Bury))
+                Some(TmemoStateAction::CardResponse(ReviewAnswer::Bury, None))
+            } else {
+                None
+            }
+        }
+        (KeyCode::Char('s'), KeyModifiers::NONE) => {
+            if state.current_state.deck.review_index.is_some() {
+                Some(TmemoStateAction::SuspendCard(current_timestamp()))
             } else {
                 None
             }
@@ -713,6 +956,11 @@ fn to_review_action(event: KeyEvent, state: &ApplicationState) -> Option<TmemoSt
                 && !state.current_state.review_show_back
             {
                 Some(TmemoStateAction::ShowBack)
+            } else if state.current_state.deck.review_index.is_none()
+                && state.current_state.deck.active_review_count() > 0
+            {
+                // Waiting for a learning step to become due
+                Some(TmemoStateAction::RefreshReview(current_timestamp()))
             } else if state.current_state.deck.review_index.is_none() {
                 Some(TmemoStateAction::ExitReview)
             } else {
@@ -781,9 +1029,13 @@ pub fn to_key_action(event: KeyEvent, state: &ApplicationState) -> Option<TmemoS
     match state.current_state.view {
         TMemoStateView::Main => to_main_action(event, state),
         TMemoStateView::Review => to_review_action(event, state),
-        TMemoStateView::Hotkeys => to_hotkeys_action(event, state),
+        TMemoStateView::Hotkeys | TMemoStateView::Stats | TMemoStateView::Heatmap => {
+            to_hotkeys_action(event, state)
+        }
         TMemoStateView::Find => to_find_action(event, state),
         TMemoStateView::Edit => to_edit_action(event, state),
+        TMemoStateView::Leeches => to_leeches_action(event, state),
+        TMemoStateView::Relink => to_relink_action(event, state),
     }
 }
 
@@ -792,6 +1044,7 @@ mod tests {
     use super::{to_key_action, EditMode};
     use crate::card::{Card, CardCollection, CardContent};
     use crate::date::Date;
+    use crate::deck::{NewCardPosition, ReviewOrder};
     use crate::fsrs::{FSRSState, ReviewAnswer};
     use crate::state::{ApplicationState, TMemoStateView, TmemoStateAction};
     use core::panic;
@@ -810,6 +1063,11 @@ mod tests {
                 editable: true,
                 base: None,
                 cloze_index: None,
+                inline_schedules: vec![],
+                id: None,
+                tags: vec![],
+                preset: None,
+                reverse: false,
             },
             fsrs_state: FSRSState::new(default_date()),
         }
@@ -824,6 +1082,11 @@ mod tests {
                 editable: true,
                 base: None,
                 cloze_index: None,
+                inline_schedules: vec![],
+                id: None,
+                tags: vec![],
+                preset: None,
+                reverse: false,
             },
             fsrs_state: FSRSState::new(default_date()),
         }
@@ -867,6 +1130,109 @@ mod tests {
         assert_eq!(app_state.current_state.view, TMemoStateView::Review);
     }
 
+    #[test]
+    fn suspending_and_unsuspending_works() {
+        let mut state = ApplicationState::new();
+        let cards = vec![new_card("front1"), new_card("front2")];
+        state.process(TmemoStateAction::ReplaceCards(
+            CardCollection::from(cards).unwrap(),
+        ));
+        state.process(TmemoStateAction::StartReview);
+        state.process(TmemoStateAction::SuspendCard(0));
+        state.process(TmemoStateAction::CardResponse(ReviewAnswer::Bury, None));
+        assert_eq!(state.current_state.deck.active_review_count(), 0);
+        state.process(TmemoStateAction::ExitReview);
+        state.process(TmemoStateAction::StartReview);
+        assert_eq!(state.current_state.deck.active_review_count(), 0);
+        state.process(TmemoStateAction::ExitReview);
+
+        state.process(TmemoStateAction::EnterView(TMemoStateView::Find));
+        state.process(TmemoStateAction::Unsuspend);
+        state.process(TmemoStateAction::Down);
+        state.process(TmemoStateAction::Unsuspend);
+        state.process(TmemoStateAction::EnterView(TMemoStateView::Main));
+        state.process(TmemoStateAction::StartReview);
+        assert_eq!(state.current_state.deck.active_review_count(), 2);
+    }
+
+    #[test]
+    fn relink_view_works() {
+        let mut state = ApplicationState::new();
+        let cards = vec![new_card("front1"), new_card("front2")];
+        state.process(TmemoStateAction::ReplaceCards(
+            CardCollection::from(cards).unwrap(),
+        ));
+        for card in state.current_state.deck.cards.iter_mut() {
+            card.fsrs_state.stability = 5.0;
+        }
+        state.current_state.deck.relink_threshold = 2.0;
+        let cards = vec![new_card("front1 changed"), new_card("front2 changed")];
+        state.process(TmemoStateAction::ReplaceCards(
+            CardCollection::from(cards).unwrap(),
+        ));
+        assert_eq!(state.current_state.deck.relink_proposals.len(), 2);
+
+        state.process(TmemoStateAction::EnterView(TMemoStateView::Relink));
+        state.process(TmemoStateAction::Down);
+        assert_eq!(state.current_state.relink_index, 1);
+        state.process(TmemoStateAction::RejectRelink);
+        assert_eq!(state.current_state.relink_index, 0);
+        state.process(TmemoStateAction::AcceptRelink);
+        assert!(state.current_state.deck.relink_proposals.is_empty());
+        assert_eq!(state.current_state.deck.orphans.len(), 1);
+    }
+
+    #[test]
+    fn leech_view_works() {
+        let mut state = ApplicationState::new();
+        let mut cards = vec![new_card("front1"), new_card("front2"), new_card("front3")];
+        cards[0].fsrs_state.leech = true;
+        cards[2].fsrs_state.leech = true;
+        state.process(TmemoStateAction::ReplaceCards(
+            CardCollection::from(cards).unwrap(),
+        ));
+        state.process(TmemoStateAction::EnterView(TMemoStateView::Leeches));
+        state.process(TmemoStateAction::Down);
+        assert_eq!(state.current_state.leech_index, 1);
+        state.process(TmemoStateAction::Down);
+        assert_eq!(state.current_state.leech_index, 1);
+        state.process(TmemoStateAction::ClearLeech);
+        assert_eq!(state.current_state.leech_index, 0);
+        assert_eq!(state.current_state.deck.leech_indices().len(), 1);
+        state.process(TmemoStateAction::StartLeechEdit);
+        assert_eq!(state.current_state.view, TMemoStateView::Edit);
+        assert_eq!(
+            state
+                .current_state
+                .current_card
+                .as_ref()
+                .unwrap()
+                .content
+                .front,
+            "front1"
+        );
+    }
+
+    #[test]
+    fn review_order_cycles() {
+        let mut state = ApplicationState::new();
+        state.process(TmemoStateAction::CycleReviewOrder);
+        assert_eq!(
+            state.current_state.deck.review_order,
+            ReviewOrder::LowestRetrievability
+        );
+        state.process(TmemoStateAction::CycleNewCardPosition);
+        assert_eq!(
+            state.current_state.deck.new_card_position,
+            NewCardPosition::First
+        );
+        state.process(TmemoStateAction::Undo);
+        assert_eq!(
+            state.current_state.deck.new_card_position,
+            NewCardPosition::Mixed
+        );
+    }
+
     #[test]
     fn moving_works() {
         let mut state = ApplicationState::new();
@@ -1099,7 +1465,7 @@ mod tests {
         state.process(TmemoStateAction::ReplaceCards(collection));
         state.process(TmemoStateAction::StartReview);
         state.process(TmemoStateAction::ShowBack);
-        state.process(TmemoStateAction::CardResponse(ReviewAnswer::Easy));
+        state.process(TmemoStateAction::CardResponse(ReviewAnswer::Easy, None));
         state.process(TmemoStateAction::StartBaseEdit(EditMode::EditFront));
         state.process(TmemoStateAction::FinishEdit(true));
         let current_card = state.current_state.current_card.clone().unwrap();
@@ -1126,14 +1492,14 @@ mod tests {
         assert!(state.current_state.deck.review_index.is_some());
         state.process(TmemoStateAction::ShowBack);
         assert!(state.current_state.review_show_back);
-        state.process(TmemoStateAction::CardResponse(ReviewAnswer::Good));
+        state.process(TmemoStateAction::CardResponse(ReviewAnswer::Good, None));
         assert_eq!(state.current_state.deck.active_review_count(), 5);
         assert_eq!(state.current_state.review_show_back, false);
-        state.process(TmemoStateAction::CardResponse(ReviewAnswer::Good));
-        state.process(TmemoStateAction::CardResponse(ReviewAnswer::Good));
-        state.process(TmemoStateAction::CardResponse(ReviewAnswer::Good));
-        state.process(TmemoStateAction::CardResponse(ReviewAnswer::Good));
-        state.process(TmemoStateAction::CardResponse(ReviewAnswer::Good));
+        state.process(TmemoStateAction::CardResponse(ReviewAnswer::Good, None));
+        state.process(TmemoStateAction::CardResponse(ReviewAnswer::Good, None));
+        state.process(TmemoStateAction::CardResponse(ReviewAnswer::Good, None));
+        state.process(TmemoStateAction::CardResponse(ReviewAnswer::Good, None));
+        state.process(TmemoStateAction::CardResponse(ReviewAnswer::Good, None));
         assert_eq!(state.current_state.deck.active_review_count(), 0);
         assert_eq!(state.current_state.deck.review_index, None);
         state.process(TmemoStateAction::StartReview);
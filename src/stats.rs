@@ -0,0 +1,341 @@
+use crate::date::Date;
+use crate::deck::Deck;
+use crate::fsrs::ReviewAnswer;
+use std::collections::BTreeMap;
+
+/// Cards with an interval of at least this many days are mature
+pub const MATURE_INTERVAL: i32 = 21;
+/// Number of days shown in the forecast and review history
+pub const STATS_DAYS: usize = 30;
+
+const STABILITY_BUCKETS: [(f64, &str); 9] = [
+    (1.0, "<1d"),
+    (3.0, "<3d"),
+    (7.0, "<1w"),
+    (14.0, "<2w"),
+    (30.0, "<1m"),
+    (90.0, "<3m"),
+    (180.0, "<6m"),
+    (365.0, "<1y"),
+    (f64::INFINITY, "1y+"),
+];
+
+const DIFFICULTY_LABELS: [&str; 9] = ["1", "2", "3", "4", "5", "6", "7", "8", "9"];
+
+#[derive(Debug, Default, PartialEq)]
+pub struct CardCounts {
+    pub new: u64,
+    pub learning: u64,
+    pub young: u64,
+    pub mature: u64,
+    pub suspended: u64,
+}
+
+#[derive(Debug)]
+pub struct DeckStats {
+    pub counts: CardCounts,
+    /// Review cards due on each of the next days, overdue cards are counted for today
+    pub due_forecast: Vec<u64>,
+    /// Answers given on each of the previous days, oldest first and today last
+    pub reviews_per_day: Vec<u64>,
+    /// Share of reviews that were not forgotten during the previous days
+    pub month_retention: Option<f64>,
+    pub total_retention: Option<f64>,
+    pub stability: Vec<(&'static str, u64)>,
+    pub difficulty: Vec<(&'static str, u64)>,
+}
+
+// Share of the first answers of the day that weren't Again, the first review of a card is not counted
+fn true_retention(deck: &Deck, since: Option<Date>) -> Option<f64> {
+    let mut correct = 0;
+    let mut total = 0;
+    for card in &deck.cards {
+        let log = &card.fsrs_state.review_log;
+        for i in 1..log.len() {
+            let item = &log[i];
+            if item.day == log[i - 1].day || item.answer == ReviewAnswer::Bury {
+                continue;
+            }
+            if since.is_some_and(|since| item.day.day < since.day) {
+                continue;
+            }
+            total += 1;
+            if item.answer != ReviewAnswer::Again {
+                correct += 1;
+            }
+        }
+    }
+
+    if total > 0 {
+        Some(correct as f64 / total as f64)
+    } else {
+        None
+    }
+}
+
+impl DeckStats {
+    pub fn new(deck: &Deck, date: Date) -> DeckStats {
+        let mut counts = CardCounts::default();
+        let mut due_forecast = vec![0; STATS_DAYS];
+        let mut reviews_per_day = vec![0; STATS_DAYS];
+        let mut stability: Vec<(&'static str, u64)> = STABILITY_BUCKETS
+            .iter()
+            .map(|(_, label)| (*label, 0))
+            .collect();
+        let mut difficulty: Vec<(&'static str, u64)> =
+            DIFFICULTY_LABELS.iter().map(|label| (*label, 0)).collect();
+
+        for card in &deck.cards {
+            let state = &card.fsrs_state;
+            for item in &state.review_log {
+                let days_ago = date.day - item.day.day;
+                if item.answer != ReviewAnswer::Bury
+                    && days_ago >= 0
+                    && days_ago < STATS_DAYS as i32
+                {
+                    reviews_per_day[STATS_DAYS - 1 - days_ago as usize] += 1;
+                }
+            }
+
+            if state.is_suspended(&date) {
+                counts.suspended += 1;
+                continue;
+            }
+            if state.is_new() {
+                counts.new += 1;
+                continue;
+            }
+
+            if state.learning_step.is_some() {
+                counts.learning += 1;
+            } else if state.review_date.day - state.last_review.day >= MATURE_INTERVAL {
+                counts.mature += 1;
+            } else {
+                counts.young += 1;
+            }
+
+            let days_until = (state.review_date.day - date.day).max(0);
+            if days_until < STATS_DAYS as i32 {
+                due_forecast[days_until as usize] += 1;
+            }
+
+            let bucket = STABILITY_BUCKETS
+                .iter()
+                .position(|(limit, _)| state.stability < *limit)
+                .unwrap();
+            stability[bucket].1 += 1;
+            let bucket = (state.difficulty.floor() as usize).clamp(1, 9) - 1;
+            difficulty[bucket].1 += 1;
+        }
+
+        DeckStats {
+            counts,
+            due_forecast,
+            reviews_per_day,
+            month_retention: true_retention(deck, date.checked_add_days(1 - STATS_DAYS as i32)),
+            total_retention: true_retention(deck, None),
+            stability,
+            difficulty,
+        }
+    }
+}
+
+/// Number of answers given on each day
+pub fn reviews_by_day(deck: &Deck) -> BTreeMap<i32, u64> {
+    let mut map = BTreeMap::new();
+    for card in &deck.cards {
+        for item in &card.fsrs_state.review_log {
+            if item.answer != ReviewAnswer::Bury {
+                *map.entry(item.day.day).or_insert(0) += 1;
+            }
+        }
+    }
+    map
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Streaks {
+    /// Days in a row with reviews up to today, or up to yesterday when nothing has been reviewed today yet
+    pub current: u32,
+    pub longest: u32,
+}
+
+pub fn streaks(reviews: &BTreeMap<i32, u64>, date: Date) -> Streaks {
+    let mut longest = 0;
+    let mut streak = 0;
+    let mut previous_day = None;
+    for day in reviews.keys() {
+        if previous_day == Some(day - 1) {
+            streak += 1;
+        } else {
+            streak = 1;
+        }
+        longest = longest.max(streak);
+        previous_day = Some(*day);
+    }
+
+    let mut day = if reviews.contains_key(&date.day) {
+        date.day
+    } else {
+        date.day - 1
+    };
+    let mut current = 0;
+    while reviews.contains_key(&day) {
+        current += 1;
+        day -= 1;
+    }
+
+    Streaks { current, longest }
+}
+
+const HEATMAP_LEVELS: [char; 5] = ['·', '░', '▒', '▓', '█'];
+const WEEKDAYS: [&str; 7] = ["Mon", "Tue", "Wed", "Thu", "Fri", "Sat", "Sun"];
+
+/// Calendar of the reviews with a column for each week and a row for each weekday, the current week last
+pub fn heatmap_lines(reviews: &BTreeMap<i32, u64>, date: Date, weeks: usize) -> Vec<String> {
+    // Day 0 is Monday, January 1st of year 1
+    let weekday = date.day.rem_euclid(7);
+    let first_day = date.day - weekday - 7 * (weeks as i32 - 1);
+    let max = reviews
+        .range(first_day..=date.day)
+        .map(|(_, count)| *count)
+        .max()
+        .unwrap_or(0);
+
+    WEEKDAYS
+        .iter()
+        .enumerate()
+        .map(|(row, name)| {
+            let mut line = format!("{} ", name);
+            for week in 0..weeks as i32 {
+                let day = first_day + week * 7 + row as i32;
+                if day > date.day {
+                    line.push(' ');
+                    continue;
+                }
+                let count = reviews.get(&day).copied().unwrap_or(0);
+                let level = if count == 0 {
+                    0
+                } else {
+                    // Split the days with reviews into four levels relative to the busiest day
+                    ((count * 4).div_ceil(max) as usize).clamp(1, 4)
+                };
+                line.push(HEATMAP_LEVELS[level]);
+            }
+            line
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::card::Card;
+    use crate::fsrs::{FSRSState, ReviewLogItem};
+
+    fn log_item(answer: ReviewAnswer, day: Date) -> ReviewLogItem {
+        ReviewLogItem {
+            answer,
+            day,
+            timestamp: None,
+            duration: None,
+        }
+    }
+
+    #[test]
+    fn stats_are_computed() {
+        let date = Date::from_yo_opt(2024, 100).unwrap();
+        let mut deck = Deck::new();
+        deck.cards.push(Card::new());
+
+        let mut young = Card::new();
+        young.fsrs_state = FSRSState::new(date.checked_add_days(-10).unwrap());
+        young.fsrs_state.stability = 5.0;
+        young.fsrs_state.difficulty = 4.5;
+        young.fsrs_state.last_review = date.checked_add_days(-2).unwrap();
+        young.fsrs_state.review_date = date.checked_add_days(3).unwrap();
+        young.fsrs_state.review_log = vec![
+            log_item(ReviewAnswer::Good, date.checked_add_days(-10).unwrap()),
+            log_item(ReviewAnswer::Again, date.checked_add_days(-5).unwrap()),
+            log_item(ReviewAnswer::Good, date.checked_add_days(-5).unwrap()),
+            log_item(ReviewAnswer::Good, date.checked_add_days(-2).unwrap()),
+        ];
+        deck.cards.push(young);
+
+        let mut mature = Card::new();
+        mature.fsrs_state = FSRSState::new(date.checked_add_days(-100).unwrap());
+        mature.fsrs_state.stability = 50.0;
+        mature.fsrs_state.difficulty = 9.5;
+        mature.fsrs_state.last_review = date.checked_add_days(-40).unwrap();
+        mature.fsrs_state.review_date = date.checked_add_days(-1).unwrap();
+        mature.fsrs_state.review_log = vec![
+            log_item(ReviewAnswer::Good, date.checked_add_days(-100).unwrap()),
+            log_item(ReviewAnswer::Good, date.checked_add_days(-40).unwrap()),
+        ];
+        deck.cards.push(mature);
+
+        let mut suspended = Card::new();
+        suspended.fsrs_state.suspend(None);
+        deck.cards.push(suspended);
+
+        let stats = DeckStats::new(&deck, date);
+        assert_eq!(
+            stats.counts,
+            CardCounts {
+                new: 1,
+                learning: 0,
+                young: 1,
+                mature: 1,
+                suspended: 1,
+            }
+        );
+        assert_eq!(stats.due_forecast[0], 1);
+        assert_eq!(stats.due_forecast[3], 1);
+        assert_eq!(stats.reviews_per_day[STATS_DAYS - 1 - 5], 2);
+        assert_eq!(stats.reviews_per_day.iter().sum::<u64>(), 4);
+        assert_eq!(stats.month_retention, Some(0.5));
+        assert_eq!(stats.total_retention, Some(2.0 / 3.0));
+        assert_eq!(stats.stability[3], ("<2w", 0));
+        assert_eq!(stats.stability[2], ("<1w", 1));
+        assert_eq!(stats.difficulty[3], ("4", 1));
+        assert_eq!(stats.difficulty[8], ("9", 1));
+    }
+
+    #[test]
+    fn streaks_are_computed() {
+        let date = Date::from_yo_opt(2024, 100).unwrap();
+        let mut reviews = BTreeMap::new();
+        for day in [-10, -9, -8, -7, -3, -2, -1] {
+            reviews.insert(date.day + day, 5);
+        }
+        assert_eq!(
+            streaks(&reviews, date),
+            Streaks {
+                current: 3,
+                longest: 4
+            }
+        );
+        reviews.insert(date.day, 1);
+        assert_eq!(streaks(&reviews, date).current, 4);
+        reviews.remove(&(date.day - 1));
+        assert_eq!(streaks(&reviews, date).current, 1);
+        assert_eq!(streaks(&BTreeMap::new(), date).longest, 0);
+    }
+
+    #[test]
+    fn heatmap_is_drawn() {
+        // 2024-04-10 is a Wednesday
+        let date = Date::from_ymd_opt(2024, 4, 10).unwrap();
+        let mut reviews = BTreeMap::new();
+        reviews.insert(date.day, 8);
+        reviews.insert(date.day - 1, 1);
+        reviews.insert(date.day - 7, 4);
+        let lines = heatmap_lines(&reviews, date, 2);
+        assert_eq!(lines.len(), 7);
+        assert_eq!(lines[0], "Mon ··");
+        assert_eq!(lines[1], "Tue ·░");
+        assert_eq!(lines[2], "Wed ▒█");
+        assert_eq!(lines[3], "Thu · ");
+        assert_eq!(lines[6], "Sun · ");
+    }
+}
@@ -0,0 +1,128 @@
+use crate::parsing::IgnoredRanges;
+use std::ops::Range;
+
+fn is_tag_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '_' | '-' | '/')
+}
+
+// Positions of the tags in the text including the #, tags in code and HTML such as comments are skipped
+fn tag_ranges(text: &str) -> Vec<Range<usize>> {
+    let mut ranges = vec![];
+    if !text.contains('#') {
+        return ranges;
+    }
+    let ignored = IgnoredRanges::new(text);
+
+    let mut previous = ' ';
+    let mut chars = text.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        if c == '#' && previous.is_whitespace() && !ignored.contains(i) {
+            let mut end = i + 1;
+            while let Some((j, next)) = chars.peek() {
+                if !is_tag_char(*next) {
+                    break;
+                }
+                end = j + next.len_utf8();
+                chars.next();
+            }
+            let tag = text[i + 1..end].trim_end_matches('/');
+            // Obsidian doesn't count numbers such as #1 as tags
+            if tag.chars().any(|x| !x.is_numeric() && x != '/') {
+                ranges.push(i..i + 1 + tag.len());
+            }
+            previous = '#';
+            continue;
+        }
+        previous = c;
+    }
+    ranges
+}
+
+/// Tags such as #spanish or #languages/spanish in the text without the #
+pub fn find_tags(text: &str) -> Vec<String> {
+    tag_ranges(text)
+        .into_iter()
+        .map(|x| text[x.start + 1..x.end].to_string())
+        .collect()
+}
+
+/// The text without the tags and the spaces around them
+pub fn strip_tags(text: &str) -> String {
+    let mut output = String::with_capacity(text.len());
+    let mut previous_end = 0;
+    for range in tag_ranges(text) {
+        output.push_str(&text[previous_end..range.start]);
+        let rest = &text[range.end..];
+        if rest.starts_with([' ', '\t'])
+            && (output.is_empty() || output.ends_with(char::is_whitespace))
+        {
+            previous_end = range.end + 1;
+        } else {
+            previous_end = range.end;
+            if rest.is_empty() || rest.starts_with('\n') {
+                output.truncate(output.trim_end_matches([' ', '\t']).len());
+            }
+        }
+    }
+    output.push_str(&text[previous_end..]);
+    output
+}
+
+/// Adds the tags that aren't in the list yet, tags are compared without case
+pub fn merge_tags(tags: &mut Vec<String>, new_tags: &[String]) {
+    for tag in new_tags {
+        if !tags.iter().any(|x| x.to_lowercase() == tag.to_lowercase()) {
+            tags.push(tag.clone());
+        }
+    }
+}
+
+/// The filter matches the tag and the tags nested under it, #languages matches languages/spanish
+pub fn tag_matches(tag: &str, filter: &str) -> bool {
+    let tag = tag.to_lowercase();
+    let filter = filter.trim_start_matches('#').to_lowercase();
+    tag == filter || tag.starts_with(&(filter + "/"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tags_are_found() {
+        assert_eq!(
+            find_tags("#spanish casa:: house #languages/spanish/ #1 a#b"),
+            vec!["spanish", "languages/spanish"]
+        );
+        assert_eq!(find_tags("# Heading"), Vec::<String>::new());
+        assert_eq!(
+            find_tags("`#include` and\n```\n#define\n```\n#c"),
+            vec!["c"]
+        );
+        assert_eq!(find_tags("päivä #sää"), vec!["sää"]);
+        assert_eq!(
+            find_tags(
+                "~~~\n#define\n~~~\ntext\n\n    #indented\n\n<!-- #hidden -->\na <!-- #b --> #c"
+            ),
+            vec!["c"]
+        );
+    }
+
+    #[test]
+    fn tags_are_stripped() {
+        assert_eq!(strip_tags("casa #spanish"), "casa");
+        assert_eq!(strip_tags("#spanish casa"), "casa");
+        assert_eq!(strip_tags("to #verb eat #a #b\nnext"), "to eat\nnext");
+        assert_eq!(strip_tags("`#include`"), "`#include`");
+    }
+
+    #[test]
+    fn tags_are_matched() {
+        assert!(tag_matches("languages/spanish", "#languages"));
+        assert!(tag_matches("Spanish", "spanish"));
+        assert!(!tag_matches("languages2", "languages"));
+        let mut tags = vec!["a".to_string()];
+        merge_tags(&mut tags, &["A".to_string(), "b".to_string()]);
+        assert_eq!(tags, vec!["a", "b"]);
+    }
+}